@@ -1,6 +1,8 @@
+use super::block_dev::{read_block_async, AsyncBlockDevice};
 use super::{BlockDevice, BLOCK_SZ};
-use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::Cell;
 use lazy_static::*;
 use spin::Mutex;
 
@@ -14,6 +16,10 @@ pub struct BlockCache {
     block_device: Arc<dyn BlockDevice>,
     /// whether the block is dirty
     modified: bool,
+    /// CLOCK "second chance" bit: set on every access, cleared by the clock
+    /// hand as it sweeps past looking for a victim. A `Cell` because
+    /// [`Self::get_ref`] only borrows `self` immutably.
+    referenced: Cell<bool>,
 }
 
 impl BlockCache {
@@ -26,6 +32,23 @@ impl BlockCache {
             block_id,
             block_device,
             modified: false,
+            referenced: Cell::new(true),
+        }
+    }
+    /// Async counterpart of [`Self::new`]: fills the cache via
+    /// [`AsyncBlockDevice::poll_read`] instead of the synchronous
+    /// `read_block`, so a cache miss can be `.await`-ed (and the waiting
+    /// task suspended, see `crate::task::executor::block_on` in the `os`
+    /// crate) instead of spinning on disk latency.
+    pub async fn new_async<D: AsyncBlockDevice>(block_id: usize, block_device: Arc<D>) -> Self {
+        let mut cache = [0u8; BLOCK_SZ];
+        read_block_async(&*block_device, block_id, &mut cache).await;
+        Self {
+            cache,
+            block_id,
+            block_device: block_device as Arc<dyn BlockDevice>,
+            modified: false,
+            referenced: Cell::new(true),
         }
     }
     /// Get the address of an offset inside the cached block data
@@ -40,10 +63,21 @@ impl BlockCache {
     {
         let type_size = core::mem::size_of::<T>();
         assert!(offset + type_size <= BLOCK_SZ); // 确认该数据结构被整个包含在磁盘块及其缓冲区之中
+        self.referenced.set(true);
         let addr = self.addr_of_offset(offset);
         unsafe { &*(addr as *const T) }
     }
 
+    /// Whether the CLOCK hand should give this block a second chance
+    fn referenced(&self) -> bool {
+        self.referenced.get()
+    }
+
+    /// Clear the second-chance bit; called by the CLOCK hand as it sweeps by
+    fn clear_referenced(&self) {
+        self.referenced.set(false);
+    }
+
     /// 获取缓冲区之中的位于偏移量 offset 的一个类型为 T 的磁盘上数据结构的可变引用
     /// 由于可能会进行修改，所以在该方法需要将对应 BlockCache 对象的 modified 属性
     /// 设置为 true
@@ -54,6 +88,7 @@ impl BlockCache {
         let type_size = core::mem::size_of::<T>();
         assert!(offset + type_size <= BLOCK_SZ);
         self.modified = true; // 写 -> 脏
+        self.referenced.set(true);
         let addr = self.addr_of_offset(offset);
         unsafe { &mut *(addr as *mut T) }
     }
@@ -78,6 +113,13 @@ impl BlockCache {
             self.block_device.write_block(self.block_id, &self.cache);
         }
     }
+
+    /// Mark the block as just-accessed without going through `get_ref`/
+    /// `get_mut` (used for a plain cache hit, where the caller hasn't
+    /// necessarily dereferenced anything yet).
+    fn touch(&self) {
+        self.referenced.set(true);
+    }
 }
 
 impl Drop for BlockCache {
@@ -85,59 +127,145 @@ impl Drop for BlockCache {
         self.sync()
     }
 }
-/// Use a block cache of 16 blocks
+/// Default cache size, used by [`BlockCacheManager::new`]; pass a different
+/// size to [`BlockCacheManager::with_capacity`] instead.
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// How many blocks to pull ahead once two consecutive requests look
+/// sequential (e.g. `read_all` streaming a file in during `sys_exec`).
+const READ_AHEAD_WINDOW: usize = 4;
+
 /// 当我们要对一个磁盘块进行读写时，首先看它是否已经被载入到内存缓存中了，如果已经被载入的话则直接返回，
 /// 否则需要先读取磁盘块的数据到内存缓存中。此时，如果内存中驻留的磁盘块缓冲区的数量已满，
 /// 则需要遵循某种缓存替换算法将某个块的缓存从内存中移除，再将刚刚读到的块数据加入到内存缓存中。
-/// 我们这里使用一种类 FIFO 的简单缓存替换算法，因此在管理器中只需维护一个队列：
+///
+/// 这里使用 CLOCK（second-chance）置换算法代替朴素的 FIFO：`hand` 是时钟指针，在队列
+/// 上循环扫描，遇到 `referenced` 位为真的块就将其清零并跳过（给它第二次机会），直到找到
+/// 一个 `referenced` 为假且强引用计数为 1（没有被别处持有）的块将其换出。
 pub struct BlockCacheManager {
     /// 管理块编号和块缓存的二元组
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    queue: Vec<(usize, Arc<Mutex<BlockCache>>)>,
+    /// 缓存容量上限
+    capacity: usize,
+    /// CLOCK 指针，记录下一轮扫描应当从哪里开始
+    hand: usize,
+    /// 上一次被请求的块号，用来判断当前访问是否是一次连续（顺序）访问
+    last_requested: Option<usize>,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
+        Self::with_capacity(BLOCK_CACHE_SIZE)
+    }
+
+    /// Create a manager that holds at most `capacity` cached blocks.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            queue: VecDeque::new(),
+            queue: Vec::new(),
+            capacity,
+            hand: 0,
+            last_requested: None,
+        }
+    }
+
+    /// Sweep the CLOCK hand until it finds an unreferenced, singly-owned
+    /// block and evict it, returning the freed slot's index.
+    fn evict(&mut self) -> usize {
+        let len = self.queue.len();
+        let mut swept = 0;
+        loop {
+            if self.hand >= len {
+                self.hand = 0;
+            }
+            let (_, cache) = &self.queue[self.hand];
+            if Arc::strong_count(cache) == 1 {
+                if cache.lock().referenced() {
+                    cache.lock().clear_referenced();
+                    self.hand += 1;
+                } else {
+                    return self.hand;
+                }
+            } else {
+                self.hand += 1;
+            }
+            swept += 1;
+            if swept > 2 * len {
+                panic!("Run out of BlockCache!");
+            }
+        }
+    }
+
+    /// Evict if necessary, then load `block_id` into a fresh `BlockCache`
+    /// and insert it. Assumes `block_id` isn't already cached.
+    fn load(&mut self, block_id: usize, block_device: &Arc<dyn BlockDevice>) -> Arc<Mutex<BlockCache>> {
+        if self.queue.len() == self.capacity {
+            let idx = self.evict();
+            self.queue.remove(idx);
+            if self.hand > idx {
+                self.hand -= 1;
+            }
+        }
+        let block_cache = Arc::new(Mutex::new(BlockCache::new(
+            block_id,
+            Arc::clone(block_device),
+        )));
+        self.queue.push((block_id, Arc::clone(&block_cache)));
+        block_cache
+    }
+
+    /// Cache-hit lookup only; never touches the block device. Used by the
+    /// async path so the global manager lock doesn't need to stay held
+    /// across an `.await` on a miss.
+    fn try_get(&mut self, block_id: usize) -> Option<Arc<Mutex<BlockCache>>> {
+        let pair = self.queue.iter().find(|pair| pair.0 == block_id)?;
+        pair.1.lock().touch();
+        Some(Arc::clone(&pair.1))
+    }
+
+    /// Make room for one more block (evicting if at capacity) and insert an
+    /// already-loaded `cache`. Paired with `try_get` by the async path.
+    fn insert(&mut self, block_id: usize, cache: Arc<Mutex<BlockCache>>) {
+        if self.queue.len() == self.capacity {
+            let idx = self.evict();
+            self.queue.remove(idx);
+            if self.hand > idx {
+                self.hand -= 1;
+            }
+        }
+        self.queue.push((block_id, cache));
+    }
+
+    /// Pull `block_id + 1 ..= block_id + READ_AHEAD_WINDOW` into the cache
+    /// when the last two requests were for consecutive blocks.
+    fn maybe_read_ahead(&mut self, block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+        if self.last_requested != block_id.checked_sub(1) {
+            return;
+        }
+        for offset in 1..=READ_AHEAD_WINDOW {
+            let ahead_id = block_id + offset;
+            if self.queue.iter().any(|pair| pair.0 == ahead_id) {
+                continue;
+            }
+            self.load(ahead_id, block_device);
         }
     }
 
     /// 尝试从块缓存管理器之中获取一个编号为 block_id 的块的块缓存，如果找不到，会从磁盘读取
-    /// 到内存之中，有可能还会发生缓存替换:
-    ///
-    /// 从队头遍历到队尾找到第一个强引用计数恰好为1的块缓存并将其替换出去
+    /// 到内存之中，有可能还会发生缓存替换。当访问模式看起来连续时，顺带预读取后面几个块。
     pub fn get_block_cache(
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
+        let result = if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
+            pair.1.lock().touch();
             Arc::clone(&pair.1)
         } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
-            }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
-        }
+            self.load(block_id, &block_device)
+        };
+        self.maybe_read_ahead(block_id, &block_device);
+        self.last_requested = Some(block_id);
+        result
     }
 }
 
@@ -157,6 +285,27 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
+/// Async counterpart of [`get_block_cache`]: a cache hit resolves exactly
+/// like the sync version; a miss awaits [`BlockCache::new_async`] instead of
+/// blocking on `read_block`, so the caller can suspend the current task
+/// until the disk request completes rather than spinning. The global
+/// manager lock is only held for the synchronous bookkeeping immediately
+/// before and after the `.await`, never across it, so other tasks can still
+/// hit the cache while one is waiting on I/O.
+pub async fn get_block_cache_async<D: AsyncBlockDevice>(
+    block_id: usize,
+    block_device: Arc<D>,
+) -> Arc<Mutex<BlockCache>> {
+    if let Some(cache) = BLOCK_CACHE_MANAGER.lock().try_get(block_id) {
+        return cache;
+    }
+    let cache = Arc::new(Mutex::new(
+        BlockCache::new_async(block_id, Arc::clone(&block_device)).await,
+    ));
+    BLOCK_CACHE_MANAGER.lock().insert(block_id, Arc::clone(&cache));
+    cache
+}
+
 /// Sync all block cache to block device
 pub fn block_cache_sync_all() {
     let manager = BLOCK_CACHE_MANAGER.lock();
@@ -164,3 +313,137 @@ pub fn block_cache_sync_all() {
         cache.lock().sync();
     }
 }
+
+/// Marks a valid, fully-written commit record in the journal's last slot;
+/// anything else there means no transaction is pending replay.
+const JOURNAL_MAGIC: u32 = 0x6a_6e_6c_31; // b"jnl1"
+
+/// How many blocks a single transaction may cover; bounds the commit
+/// record's fixed-size block id table and the journal region's size
+/// (`JOURNAL_MAX_BLOCKS` data slots plus one trailing commit-record slot).
+pub const JOURNAL_MAX_BLOCKS: usize = 15;
+
+/// On-disk commit record, written to the journal region's last block only
+/// after every other block of the transaction has been written before it.
+/// Its presence (a matching `magic`) is therefore the signal that the
+/// whole transaction is safely durable and ready to replay.
+#[repr(C)]
+struct CommitRecord {
+    magic: u32,
+    count: u32,
+    block_ids: [u32; JOURNAL_MAX_BLOCKS],
+}
+
+/// A multi-block metadata update in progress (e.g. `Inode::link`/`unlink`,
+/// or allocating a new file, which each touch a bitmap block, an inode
+/// block and a directory data block together). `BlockCache` only flushes a
+/// dirty block on `Drop`/`block_cache_sync_all`, so without this a crash
+/// between two of those writes can leave easy-fs metadata partially
+/// updated and inconsistent — e.g. a directory entry pointing at an inode
+/// whose bitmap bit was never set. `Transaction` gives such updates the
+/// atomicity Ext4-style journaling filesystems get from a write-ahead log:
+/// buffer which blocks were touched, then on [`Self::commit`] write them
+/// (and a commit record) to a reserved journal region first, and only then
+/// checkpoint them to their home locations. [`replay_journal`] recovers
+/// from a crash that happened mid-checkpoint by redoing it on next mount.
+pub struct Transaction {
+    journal_start: usize,
+    dirty_blocks: Vec<usize>,
+}
+
+/// Begin a transaction. `journal_start` is the first block of the
+/// filesystem's reserved journal region, which must be `JOURNAL_MAX_BLOCKS
+/// + 1` blocks long (one slot per buffered block plus the trailing commit
+/// record).
+pub fn transaction_begin(journal_start: usize) -> Transaction {
+    Transaction {
+        journal_start,
+        dirty_blocks: Vec::new(),
+    }
+}
+
+impl Transaction {
+    /// Record that `block_id` was (or is about to be) modified through the
+    /// ordinary `get_block_cache`/`modify` path as part of this
+    /// transaction. Only the set of touched block ids is tracked here;
+    /// the actual writes still go through the block cache as usual.
+    pub fn touch(&mut self, block_id: usize) {
+        if !self.dirty_blocks.contains(&block_id) {
+            self.dirty_blocks.push(block_id);
+        }
+    }
+
+    /// Write every touched block's current (cached) contents to the
+    /// journal region followed by a commit record, then checkpoint them to
+    /// their home locations and erase the commit record.
+    ///
+    /// If power is lost before the commit record is written, `replay_journal`
+    /// finds no valid record and the (untouched) home locations are still
+    /// consistent. If power is lost afterwards — including mid-checkpoint —
+    /// `replay_journal` finishes writing the journaled blocks to their home
+    /// locations on next mount.
+    pub fn commit(self, block_device: &Arc<dyn BlockDevice>) {
+        assert!(
+            self.dirty_blocks.len() <= JOURNAL_MAX_BLOCKS,
+            "transaction touches more blocks than the journal region can hold"
+        );
+
+        let mut record = CommitRecord {
+            magic: JOURNAL_MAGIC,
+            count: self.dirty_blocks.len() as u32,
+            block_ids: [0; JOURNAL_MAX_BLOCKS],
+        };
+        for (i, &block_id) in self.dirty_blocks.iter().enumerate() {
+            record.block_ids[i] = block_id as u32;
+            let mut buf = [0u8; BLOCK_SZ];
+            get_block_cache(block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |data: &[u8; BLOCK_SZ]| buf.copy_from_slice(data));
+            block_device.write_block(self.journal_start + i, &buf);
+        }
+        write_commit_record(block_device, self.journal_start, &record);
+
+        for &block_id in &self.dirty_blocks {
+            get_block_cache(block_id, Arc::clone(block_device))
+                .lock()
+                .sync();
+        }
+        clear_commit_record(block_device, self.journal_start);
+    }
+}
+
+fn write_commit_record(block_device: &Arc<dyn BlockDevice>, journal_start: usize, record: &CommitRecord) {
+    let mut block = [0u8; BLOCK_SZ];
+    let bytes = unsafe {
+        core::slice::from_raw_parts(record as *const _ as *const u8, core::mem::size_of::<CommitRecord>())
+    };
+    block[..bytes.len()].copy_from_slice(bytes);
+    block_device.write_block(journal_start + JOURNAL_MAX_BLOCKS, &block);
+}
+
+fn clear_commit_record(block_device: &Arc<dyn BlockDevice>, journal_start: usize) {
+    block_device.write_block(journal_start + JOURNAL_MAX_BLOCKS, &[0u8; BLOCK_SZ]);
+}
+
+/// Replay a pending transaction, if any. Meant to be called once at mount
+/// time, after the block device is available but before anything else
+/// touches the filesystem: if an unclean shutdown left a valid commit
+/// record in the journal region (`journal_start`, see [`transaction_begin`]),
+/// every block it names is copied from the journal back to its home
+/// location, and the commit record is then erased so it isn't replayed
+/// again on the next mount.
+pub fn replay_journal(block_device: &Arc<dyn BlockDevice>, journal_start: usize) {
+    let mut record_block = [0u8; BLOCK_SZ];
+    block_device.read_block(journal_start + JOURNAL_MAX_BLOCKS, &mut record_block);
+    let record = unsafe { &*(record_block.as_ptr() as *const CommitRecord) };
+    if record.magic != JOURNAL_MAGIC {
+        return;
+    }
+
+    let mut buf = [0u8; BLOCK_SZ];
+    for i in 0..record.count as usize {
+        block_device.read_block(journal_start + i, &mut buf);
+        block_device.write_block(record.block_ids[i] as usize, &buf);
+    }
+    clear_commit_record(block_device, journal_start);
+}
@@ -7,6 +7,9 @@
 //! 它可以访问实现了 BlockDevice Trait 的块设备驱动程序。
 
 use core::any::Any;
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+
 /// Trait for block devices
 /// which reads and writes data in the unit of blocks
 pub trait BlockDevice: Send + Sync + Any {
@@ -16,6 +19,37 @@ pub trait BlockDevice: Send + Sync + Any {
     fn write_block(&self, block_id: usize, buf: &[u8]);
 }
 
+/// Async counterpart of [`BlockDevice`], modeled on the split tornado-os
+/// uses for its async-virtio driver: a real implementation kicks off the
+/// request once and then returns `Poll::Pending` until the device raises
+/// its completion interrupt (waking `cx.waker()`), instead of spinning
+/// inside `read_block`/`write_block` until the data is there.
+pub trait AsyncBlockDevice: BlockDevice {
+    /// Poll a block read into `buf`. Returns `Poll::Ready(())` once the
+    /// whole block has arrived.
+    fn poll_read(&self, block_id: usize, buf: &mut [u8], cx: &mut Context<'_>) -> Poll<()>;
+    /// Poll a block write, mirroring `poll_read`.
+    fn poll_write(&self, block_id: usize, buf: &[u8], cx: &mut Context<'_>) -> Poll<()>;
+}
+
+/// `.await`-able wrapper around [`AsyncBlockDevice::poll_read`].
+pub async fn read_block_async<D: AsyncBlockDevice + ?Sized>(
+    device: &D,
+    block_id: usize,
+    buf: &mut [u8],
+) {
+    poll_fn(|cx| device.poll_read(block_id, buf, cx)).await
+}
+
+/// `.await`-able wrapper around [`AsyncBlockDevice::poll_write`].
+pub async fn write_block_async<D: AsyncBlockDevice + ?Sized>(
+    device: &D,
+    block_id: usize,
+    buf: &[u8],
+) {
+    poll_fn(|cx| device.poll_write(block_id, buf, cx)).await
+}
+
 // 块与扇区
 // 而块是文件系统存储文件时的数据单位，每个块的大小等同于一个或多个扇区。之前提到过 Linux 的Ext4文件系统的
 // 单个块大小默认为 4096 字节。在我们的 easy-fs 实现中一个块和一个扇区同为 512 字节，因此在后面的讲解中我
@@ -1,6 +1,6 @@
 use super::{
     block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    EasyFileSystem, BLOCK_SZ, DIRENT_SZ,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -8,8 +8,100 @@ use alloc::vec::Vec;
 use bitflags::bitflags;
 use spin::{Mutex, MutexGuard};
 
+/// Default owner/group and permission bits handed to a freshly `create`d,
+/// `mkdir`ed, or `symlink`ed inode. There's no process credential plumbed
+/// down to this layer yet, so everything is owned by root with a
+/// conservative rw-r--r-- for plain files (or, for a symlink's own mode,
+/// rwxrwxrwx — its *target*'s mode is what actually gates access).
+/// Directories get rwxr-xr-x instead: `check_access` requires at least one
+/// execute bit to search a directory (even for `uid == 0`), so reusing
+/// `DEFAULT_FILE_PERM` would make every `mkdir`-created directory
+/// untraversable.
+const DEFAULT_UID: u32 = 0;
+const DEFAULT_GID: u32 = 0;
+const DEFAULT_FILE_PERM: u16 = 0o644;
+const DEFAULT_DIR_PERM: u16 = 0o755;
+const DEFAULT_SYMLINK_PERM: u16 = 0o777;
+
+/// The time source `read_at`/`write_at`/`clear`/`create`/`symlink`/`link`/
+/// `unlink` stamp atime/mtime/ctime with. easy-fs has no clock of its own,
+/// so this defaults to an always-0 clock until the kernel installs a real
+/// one with [`set_clock`].
+static CLOCK: Mutex<Option<fn() -> u64>> = Mutex::new(None);
+
+/// Install the time source used to stamp inode timestamps, e.g.
+/// `easy_fs::set_clock(|| get_time_ms() as u64)` once the kernel's own
+/// timer is up. Until this is called, timestamps all read back as 0.
+pub fn set_clock(clock: fn() -> u64) {
+    *CLOCK.lock() = Some(clock);
+}
+
+fn now() -> u64 {
+    CLOCK.lock().map_or(0, |clock| clock())
+}
+
+/// Iterates the `DirEntry`s packed into a directory's raw byte stream,
+/// starting at dirent index 0. Instead of issuing one `DiskInode::read_at`
+/// (and thus one block cache lookup) per entry like a naive
+/// `for i in 0..file_count` scan, it pulls a whole `BLOCK_SZ` worth of
+/// entries at a time and only re-resolves the underlying data block once
+/// the iterator crosses into the next chunk. Backs `find_inode_id`, `ls`,
+/// and every dirent scan/compaction used by `unlink`/`rmdir`/`rename`.
+struct DirentIter<'a> {
+    disk_inode: &'a DiskInode,
+    block_device: &'a Arc<dyn BlockDevice>,
+    file_count: usize,
+    index: usize,
+    chunk: Vec<u8>,
+    chunk_start: usize,
+}
+
+impl<'a> DirentIter<'a> {
+    const ENTRIES_PER_CHUNK: usize = BLOCK_SZ / DIRENT_SZ;
+
+    fn new(disk_inode: &'a DiskInode, block_device: &'a Arc<dyn BlockDevice>) -> Self {
+        Self {
+            disk_inode,
+            block_device,
+            file_count: (disk_inode.size as usize) / DIRENT_SZ,
+            index: 0,
+            chunk: Vec::new(),
+            chunk_start: usize::MAX,
+        }
+    }
+}
+
+impl<'a> Iterator for DirentIter<'a> {
+    type Item = (usize, DirEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.file_count {
+            return None;
+        }
+        let entries_per_chunk = Self::ENTRIES_PER_CHUNK.max(1);
+        let chunk_start = (self.index / entries_per_chunk) * entries_per_chunk;
+        if chunk_start != self.chunk_start {
+            let entries_in_chunk = entries_per_chunk.min(self.file_count - chunk_start);
+            let mut buf = alloc::vec![0u8; entries_in_chunk * DIRENT_SZ];
+            self.disk_inode
+                .read_at(chunk_start * DIRENT_SZ, &mut buf, self.block_device);
+            self.chunk = buf;
+            self.chunk_start = chunk_start;
+        }
+        let offset = (self.index - self.chunk_start) * DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        dirent
+            .as_bytes_mut()
+            .copy_from_slice(&self.chunk[offset..offset + DIRENT_SZ]);
+        let item = (self.index, dirent);
+        self.index += 1;
+        Some(item)
+    }
+}
+
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
+    inode_id: u32,       // 本Inode在磁盘上的inode编号，用于生成 `..` 这样指向自身的dirent
     block_id: usize,     // 对应的DiskInode保存在磁盘上的块号
     block_offset: usize, // 对应的DiskInode保存在磁盘上的块内偏移量
     fs: Arc<Mutex<EasyFileSystem>>,
@@ -19,12 +111,14 @@ pub struct Inode {
 impl Inode {
     /// Create a vfs inode
     pub fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
@@ -50,18 +144,9 @@ impl Inode {
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
         // assert it is a directory
         assert!(disk_inode.is_dir());
-        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
-            );
-            if dirent.name() == name {
-                return Some(dirent.inode_id() as u32);
-            }
-        }
-        None
+        DirentIter::new(disk_inode, &self.block_device)
+            .find(|(_, dirent)| dirent.name() == name)
+            .map(|(_, dirent)| dirent.inode_id() as u32)
     }
 
     // 包括find在内，所有暴露给文件系统的使用者的文件系统操作（还有接下来要介绍的几种），
@@ -80,6 +165,7 @@ impl Inode {
                 // 在这里最要注意的一点是 inode_id 不是 block_id，它们之间的粒度是不一样的
                 let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
                 Arc::new(Self::new(
+                    inode_id,
                     block_id,
                     block_offset,
                     self.fs.clone(),
@@ -126,6 +212,12 @@ impl Inode {
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
                 new_inode.initialize(DiskInodeType::File);
+                new_inode.uid = DEFAULT_UID;
+                new_inode.gid = DEFAULT_GID;
+                new_inode.perm = DEFAULT_FILE_PERM;
+                new_inode.atime = now();
+                new_inode.mtime = new_inode.atime;
+                new_inode.ctime = new_inode.atime;
             });
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
@@ -146,6 +238,7 @@ impl Inode {
         block_cache_sync_all();
         // return inode
         Some(Arc::new(Self::new(
+            new_inode_id,
             block_id,
             block_offset,
             self.fs.clone(),
@@ -153,38 +246,246 @@ impl Inode {
         )))
         // release efs lock automatically by compiler
     }
+    /// Create a subdirectory named `name` under the current (directory)
+    /// inode, pre-populated with `.` (pointing at itself) and `..`
+    /// (pointing at this directory). Mirrors Unix mkdir's nlink
+    /// bookkeeping: the new directory starts at nlink 2 (its own entry
+    /// plus its `.`), and this directory gains one for the child's `..`.
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            // assert it is a directory
+            assert!(root_inode.is_dir());
+            // has an entry with this name already been created?
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        // alloc a inode for the new directory
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::Directory);
+                new_inode.nlink = 2;
+                new_inode.uid = DEFAULT_UID;
+                new_inode.gid = DEFAULT_GID;
+                new_inode.perm = DEFAULT_DIR_PERM;
+                new_inode.atime = now();
+                new_inode.mtime = new_inode.atime;
+                new_inode.ctime = new_inode.atime;
+            });
+        let new_inode = Arc::new(Self::new(
+            new_inode_id,
+            new_inode_block_id,
+            new_inode_block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ));
+        new_inode.modify_disk_inode(|disk_inode| {
+            new_inode.increase_size(2 * DIRENT_SZ as u32, disk_inode, &mut fs);
+            let dot = DirEntry::new(".", new_inode_id);
+            let dotdot = DirEntry::new("..", self.inode_id);
+            disk_inode.write_at(0, dot.as_bytes(), &self.block_device);
+            disk_inode.write_at(DIRENT_SZ, dotdot.as_bytes(), &self.block_device);
+        });
+
+        self.modify_disk_inode(|root_inode| {
+            // append the subdirectory in our own dirent list
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs);
+            let dirent = DirEntry::new(name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+            // account for the child's `..`
+            root_inode.nlink += 1;
+        });
+
+        block_cache_sync_all();
+        Some(new_inode)
+    }
+    /// Create a symlink named `link_name` under the current (directory)
+    /// inode, whose target is the literal path string `target`, stored as
+    /// the new inode's raw data exactly like a regular file's contents.
+    pub fn symlink(&self, link_name: &str, target: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            assert!(root_inode.is_dir());
+            self.find_inode_id(link_name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        // create a new inode holding the target path as its data
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::Symlink);
+                new_inode.uid = DEFAULT_UID;
+                new_inode.gid = DEFAULT_GID;
+                new_inode.perm = DEFAULT_SYMLINK_PERM;
+                new_inode.atime = now();
+                new_inode.mtime = new_inode.atime;
+                new_inode.ctime = new_inode.atime;
+            });
+        let new_inode = Arc::new(Self::new(
+            new_inode_id,
+            new_inode_block_id,
+            new_inode_block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        ));
+        new_inode.modify_disk_inode(|disk_inode| {
+            new_inode.increase_size(target.len() as u32, disk_inode, &mut fs);
+            disk_inode.write_at(0, target.as_bytes(), &self.block_device);
+        });
+
+        self.modify_disk_inode(|root_inode| {
+            // append link_name in the dirent
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs);
+            let dirent = DirEntry::new(link_name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+        });
+
+        block_cache_sync_all();
+        Some(new_inode)
+    }
+    /// Read back the target path stored by [`Self::symlink`], or `None` if
+    /// this inode isn't a symlink.
+    pub fn read_link(&self) -> Option<String> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_symlink() {
+                return None;
+            }
+            let mut buf = alloc::vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut buf, &self.block_device);
+            String::from_utf8(buf).ok()
+        })
+    }
+    /// Whether this inode is a symlink (see [`Self::symlink`]).
+    pub fn is_symlink(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+    /// Whether this inode is a directory.
+    fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+    /// Like [`Self::find`], but if the looked-up entry is a symlink,
+    /// follows it — re-resolving the stored target as another entry under
+    /// this same directory — up to `max_follows` times before giving up,
+    /// so a symlink loop (or chain longer than the caller is willing to
+    /// wait for) can't spin forever.
+    pub fn find_follow(&self, name: &str, max_follows: usize) -> Option<Arc<Inode>> {
+        let mut current = self.find(name)?;
+        for _ in 0..max_follows {
+            if !current.is_symlink() {
+                return Some(current);
+            }
+            let target = current.read_link()?;
+            current = self.find(&target)?;
+        }
+        if current.is_symlink() {
+            None
+        } else {
+            Some(current)
+        }
+    }
+    /// Resolve a `/`-separated path starting from this inode, walking one
+    /// component at a time via [`Self::find`]. Returns `None` as soon as a
+    /// component is missing, or a non-final component isn't a directory.
+    pub fn find_path(&self, path: &str) -> Option<Arc<Inode>> {
+        let mut components = path.split('/').filter(|c| !c.is_empty());
+        let mut current = self.find(components.next()?)?;
+        for component in components {
+            if !current.is_dir() {
+                return None;
+            }
+            current = current.find(component)?;
+        }
+        Some(current)
+    }
     /// List inodes under current inode
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
-            }
-            v
+            DirentIter::new(disk_inode, &self.block_device)
+                .map(|(_, dirent)| String::from(dirent.name()))
+                .collect()
         })
     }
     /// Read data from current inode
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        self.modify_disk_inode(|disk_inode| {
+            let n = disk_inode.read_at(offset, buf, &self.block_device);
+            disk_inode.atime = now();
+            n
+        })
     }
     /// Write data to current inode
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
         let size = self.modify_disk_inode(|disk_inode| {
             self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+            let written = disk_inode.write_at(offset, buf, &self.block_device);
+            // write-clears-suid/sgid: a write could smuggle privileged
+            // behavior through a setuid/setgid file, so every write drops
+            // setuid unconditionally, and setgid too when it's paired with
+            // group-execute (the convention that otherwise means mandatory
+            // locking, not "run as group").
+            disk_inode.perm &= !0o4000;
+            if disk_inode.perm & 0o010 != 0 {
+                disk_inode.perm &= !0o2000;
+            }
+            disk_inode.mtime = now();
+            disk_inode.ctime = disk_inode.mtime;
+            written
         });
         block_cache_sync_all();
         size
     }
+    /// POSIX-style access check. `uid == 0` (root) always gets read/write,
+    /// and gets execute only if some execute bit is set at all; everyone
+    /// else is checked against whichever triad (owner/group/other) applies
+    /// to them.
+    pub fn check_access(&self, uid: u32, gids: &[u32], want: AccessMode) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if uid == 0 {
+                return if want.contains(AccessMode::EXEC) {
+                    disk_inode.perm & 0o111 != 0
+                } else {
+                    true
+                };
+            }
+            let triad = if uid == disk_inode.uid {
+                (disk_inode.perm >> 6) & 0o7
+            } else if gids.contains(&disk_inode.gid) {
+                (disk_inode.perm >> 3) & 0o7
+            } else {
+                disk_inode.perm & 0o7
+            };
+            let want_bits = want.bits() as u16;
+            triad & want_bits == want_bits
+        })
+    }
     /// Clear the data in current inode
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
@@ -195,26 +496,56 @@ impl Inode {
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
+            disk_inode.mtime = now();
+            disk_inode.ctime = disk_inode.mtime;
         });
         block_cache_sync_all();
     }
 
     /// Read stat from current inode
+    ///
+    /// `ino` is derived from `(block_id, block_offset)` rather than just
+    /// `block_id`, since several `DiskInode`s can live in the same block;
+    /// using `block_id` alone would make unrelated inodes that happen to
+    /// share a block report the same `ino`. Two names `link`ed to the same
+    /// file always resolve to the same `(block_id, block_offset)`, so they
+    /// still correctly report the same `ino`.
     pub fn read_stat(&self, st: &mut Stat) {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
             st.dev = 0;
-            st.ino = self.block_id as u64;
+            st.ino = ((self.block_id as u64) << 32) | self.block_offset as u64;
             st.mode = if disk_inode.is_dir() {
                 StatMode::DIR
+            } else if disk_inode.is_symlink() {
+                StatMode::LINK
             } else {
                 StatMode::FILE
             };
 
             st.nlink = disk_inode.nlink as u32;
+            st.uid = disk_inode.uid;
+            st.gid = disk_inode.gid;
+            st.perm = disk_inode.perm;
+            st.atime = disk_inode.atime;
+            st.mtime = disk_inode.mtime;
+            st.ctime = disk_inode.ctime;
         });
     }
 
+    /// Report overall filesystem usage, driven entirely by the inode and
+    /// data bitmaps' set/total bit counts so no block needs to be scanned.
+    pub fn stat_fs(&self) -> FsStat {
+        let fs = self.fs.lock();
+        FsStat {
+            block_size: BLOCK_SZ as u32,
+            total_blocks: fs.total_data_blocks() as u64,
+            free_blocks: (fs.total_data_blocks() - fs.used_data_blocks()) as u64,
+            total_inodes: fs.total_inodes() as u64,
+            free_inodes: (fs.total_inodes() - fs.used_inodes()) as u64,
+        }
+    }
+
     /// Link a new dir entry to a file.
     /// warn: this method must be called by dir inode.
     pub fn link(&self, old_name: &str, new_name: &str) -> Result<(), &'static str> {
@@ -229,7 +560,10 @@ impl Inode {
             get_block_cache(block_id as usize, Arc::clone(&self.block_device))
                 .lock()
                 // Increase the `nlink` of target DiskInode
-                .modify(block_offset, |n: &mut DiskInode| n.nlink += 1);
+                .modify(block_offset, |n: &mut DiskInode| {
+                    n.nlink += 1;
+                    n.ctime = now();
+                });
 
             // Insert `newname` into directory.
             self.modify_disk_inode(|root_inode| {
@@ -259,13 +593,7 @@ impl Inode {
         let mut v: Vec<DirEntry> = Vec::new();
 
         self.modify_disk_inode(|root_inode| {
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    root_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device),
-                    DIRENT_SZ,
-                );
+            for (_, dirent) in DirentIter::new(root_inode, &self.block_device) {
                 if dirent.name() != name {
                     v.push(dirent);
                 } else {
@@ -300,6 +628,7 @@ impl Inode {
                 .modify(block_offset, |n: &mut DiskInode| {
                     // Decrease `nlink`.
                     n.nlink -= 1;
+                    n.ctime = now();
                     // If `nlink` is zero, free all data_block through `clear_size()`.
                     if n.nlink == 0 {
                         let size = n.size;
@@ -320,6 +649,342 @@ impl Inode {
             Err("Can't find target file!")
         }
     }
+
+    /// Remove the subdirectory `name`, refusing if it still holds any
+    /// entry besides its own `.`/`..`. Unlike [`Self::unlink`], directories
+    /// in this filesystem are never hardlinked, so the child's data blocks
+    /// are always freed outright rather than gated behind an nlink count.
+    pub fn rmdir(&self, name: &str) -> Result<(), &'static str> {
+        let mut fs = self.fs.lock();
+
+        let mut inode_id: Option<u32> = None;
+        let mut v: Vec<DirEntry> = Vec::new();
+
+        self.read_disk_inode(|root_inode| {
+            for (_, dirent) in DirentIter::new(root_inode, &self.block_device) {
+                if dirent.name() != name {
+                    v.push(dirent);
+                } else {
+                    inode_id = Some(dirent.inode_id());
+                }
+            }
+        });
+
+        let victim_inode_id = inode_id.ok_or("No such directory")?;
+        let (block_id, block_offset) = fs.get_disk_inode_pos(victim_inode_id);
+        let is_empty_dir = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(block_offset, |disk_inode: &DiskInode| {
+                assert!(disk_inode.is_dir());
+                (disk_inode.size as usize) / DIRENT_SZ <= 2
+            });
+        if !is_empty_dir {
+            return Err("Directory not empty");
+        }
+
+        self.modify_disk_inode(|root_inode| {
+            let size = root_inode.size;
+            let data_blocks_dealloc = root_inode.clear_size(&self.block_device);
+            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+
+            self.increase_size((v.len() * DIRENT_SZ) as u32, root_inode, &mut fs);
+            for (i, dirent) in v.iter().enumerate() {
+                root_inode.write_at(i * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+            }
+            // undo the nlink bump the child's `..` earned us in `mkdir`
+            root_inode.nlink -= 1;
+        });
+
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(block_offset, |victim: &mut DiskInode| {
+                let size = victim.size;
+                let data_blocks_dealloc = victim.clear_size(&self.block_device);
+                assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+                for data_block in data_blocks_dealloc.into_iter() {
+                    fs.dealloc_data(data_block);
+                }
+                victim.nlink = 0;
+            });
+
+        block_cache_sync_all();
+        Ok(())
+    }
+
+    /// Atomically rename `old_name` (a dirent of `self`) to `new_name`
+    /// under `new_dir` (which may be `self`, for a same-directory rename),
+    /// holding the single filesystem lock for the whole operation.
+    ///
+    /// By default, if `new_name` already exists it is replaced: its
+    /// `nlink` is decremented (freeing its blocks once it hits zero) and
+    /// the old dirent slot is moved over to `new_dir`.
+    /// `RenameFlags::NOREPLACE` fails instead if `new_name` already
+    /// exists. `RenameFlags::EXCHANGE` atomically swaps the two entries'
+    /// target inode ids in place — both `old_name` and `new_name` must
+    /// already exist, and neither `nlink` changes.
+    pub fn rename(
+        &self,
+        old_name: &str,
+        new_dir: &Arc<Inode>,
+        new_name: &str,
+        flags: RenameFlags,
+    ) -> Result<(), &'static str> {
+        let mut fs = self.fs.lock();
+
+        let old_inode_id = self
+            .read_disk_inode(|root_inode| self.find_inode_id(old_name, root_inode))
+            .ok_or("Can't find source file!")?;
+
+        let existing_new_inode_id =
+            new_dir.read_disk_inode(|root_inode| new_dir.find_inode_id(new_name, root_inode));
+
+        // `new_name` is already a hard link to the same inode as `old_name`
+        // (including the trivial rename("a", dir, "a")): per POSIX this is a
+        // no-op, for both the replace and the exchange case — swapping or
+        // replacing a link with itself would otherwise decrement the only
+        // remaining link to the inode and free it out from under the
+        // directory entry that's supposed to still name it.
+        if existing_new_inode_id == Some(old_inode_id) {
+            return Ok(());
+        }
+
+        if flags.contains(RenameFlags::EXCHANGE) {
+            let existing_new_inode_id =
+                existing_new_inode_id.ok_or("Can't exchange: target doesn't exist!")?;
+            new_dir.set_dirent_inode_id(new_name, old_inode_id);
+            self.set_dirent_inode_id(old_name, existing_new_inode_id);
+            block_cache_sync_all();
+            return Ok(());
+        }
+
+        if existing_new_inode_id.is_some() && flags.contains(RenameFlags::NOREPLACE) {
+            return Err("Target file already exists!");
+        }
+
+        if let Some(victim_inode_id) = existing_new_inode_id {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(victim_inode_id);
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(block_offset, |n: &mut DiskInode| {
+                    n.nlink -= 1;
+                    n.ctime = now();
+                    if n.nlink == 0 {
+                        let size = n.size;
+                        let data_blocks_dealloc = n.clear_size(&self.block_device);
+                        assert!(
+                            data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize
+                        );
+                        for data_block in data_blocks_dealloc.into_iter() {
+                            fs.dealloc_data(data_block);
+                        }
+                    }
+                });
+            new_dir.set_dirent_inode_id(new_name, old_inode_id);
+        } else {
+            new_dir.insert_dirent(new_name, old_inode_id, &mut fs);
+        }
+
+        self.remove_dirent(old_name, &mut fs);
+
+        block_cache_sync_all();
+        Ok(())
+    }
+
+    /// Overwrite the target inode id of the dirent named `name`, leaving
+    /// its position (and every other entry) untouched. Used by `rename`'s
+    /// replace and exchange paths. Returns the id it used to point at.
+    fn set_dirent_inode_id(&self, name: &str, new_inode_id: u32) -> Option<u32> {
+        let mut old_id = None;
+        self.modify_disk_inode(|root_inode| {
+            if let Some((i, dirent)) = DirentIter::new(root_inode, &self.block_device)
+                .find(|(_, dirent)| dirent.name() == name)
+            {
+                old_id = Some(dirent.inode_id());
+                let updated = DirEntry::new(name, new_inode_id);
+                root_inode.write_at(i * DIRENT_SZ, updated.as_bytes(), &self.block_device);
+            }
+        });
+        old_id
+    }
+
+    /// Append a new dirent pointing at `inode_id`. Used by `rename` when
+    /// `new_name` doesn't already exist in the destination directory.
+    fn insert_dirent(&self, name: &str, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        self.modify_disk_inode(|root_inode| {
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, fs);
+            let dirent = DirEntry::new(name, inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+        });
+    }
+
+    /// Remove the dirent named `name`, compacting the remaining entries
+    /// like `unlink` does, but without touching the removed entry's
+    /// target inode's `nlink` — used by `rename`, where the inode is
+    /// moving to another directory entry rather than losing a reference.
+    fn remove_dirent(&self, name: &str, fs: &mut MutexGuard<EasyFileSystem>) {
+        let mut v: Vec<DirEntry> = Vec::new();
+        self.modify_disk_inode(|root_inode| {
+            for (_, dirent) in DirentIter::new(root_inode, &self.block_device) {
+                if dirent.name() != name {
+                    v.push(dirent);
+                }
+            }
+        });
+        self.modify_disk_inode(|root_inode| {
+            let size = root_inode.size;
+            let data_blocks_dealloc = root_inode.clear_size(&self.block_device);
+            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+            self.increase_size((v.len() * DIRENT_SZ) as u32, root_inode, fs);
+            for (i, dirent) in v.iter().enumerate() {
+                root_inode.write_at(i * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+            }
+        });
+    }
+
+    /// Attach (or overwrite) the extended attribute `name` with `value`.
+    /// Errors if the full attribute set no longer fits in the one
+    /// dedicated attribute block each inode gets (allocated lazily here,
+    /// on the first `set_xattr` call).
+    pub fn set_xattr(&self, name: &str, value: &[u8]) -> Result<(), &'static str> {
+        let mut fs = self.fs.lock();
+        let mut attrs = self.read_xattr_block().unwrap_or_default();
+        attrs.retain(|(n, _)| n != name);
+        attrs.push((String::from(name), Vec::from(value)));
+        self.write_xattr_block(&attrs, &mut fs)?;
+        block_cache_sync_all();
+        Ok(())
+    }
+
+    /// Read back the extended attribute `name`, if it's set.
+    pub fn get_xattr(&self, name: &str) -> Option<Vec<u8>> {
+        let _fs = self.fs.lock();
+        self.read_xattr_block()?
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// List every extended attribute name set on this inode.
+    pub fn list_xattr(&self) -> Vec<String> {
+        let _fs = self.fs.lock();
+        self.read_xattr_block()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect()
+    }
+
+    /// Remove the extended attribute `name`, if it's set; a no-op if not.
+    pub fn remove_xattr(&self, name: &str) {
+        let mut fs = self.fs.lock();
+        if let Some(mut attrs) = self.read_xattr_block() {
+            let before = attrs.len();
+            attrs.retain(|(n, _)| n != name);
+            if attrs.len() != before {
+                // `write_xattr_block` can only fail by overflowing the
+                // block, and removing entries can't make that worse.
+                self.write_xattr_block(&attrs, &mut fs)
+                    .expect("removing an attribute can't overflow the block");
+                block_cache_sync_all();
+            }
+        }
+    }
+
+    /// Decode the current attribute block's contents, or `None` if no
+    /// attribute has ever been set on this inode (so no block is
+    /// allocated yet).
+    fn read_xattr_block(&self) -> Option<Vec<(String, Vec<u8>)>> {
+        let block_id = self.read_disk_inode(|disk_inode| disk_inode.xattr_block_id)?;
+        if block_id == 0 {
+            return None;
+        }
+        let mut attrs = Vec::new();
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |block: &[u8; BLOCK_SZ]| {
+                for_each_xattr(block, |name, value| {
+                    attrs.push((String::from(name), Vec::from(value)));
+                });
+            });
+        Some(attrs)
+    }
+
+    /// Encode `attrs` and write them into the attribute block, allocating
+    /// one via `fs.alloc_data` (and storing its id in the new
+    /// `xattr_block_id` pointer) the first time this inode gets an
+    /// attribute.
+    fn write_xattr_block(
+        &self,
+        attrs: &[(String, Vec<u8>)],
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> Result<(), &'static str> {
+        let mut block = [0u8; BLOCK_SZ];
+        let mut pos = 2;
+        for (name, value) in attrs {
+            if name.len() > u8::MAX as usize || value.len() > u16::MAX as usize {
+                return Err("extended attribute name/value too long");
+            }
+            let record_len = 1 + name.len() + 2 + value.len();
+            if pos + record_len > BLOCK_SZ {
+                return Err("extended attributes don't fit in one attribute block");
+            }
+            block[pos] = name.len() as u8;
+            pos += 1;
+            block[pos..pos + name.len()].copy_from_slice(name.as_bytes());
+            pos += name.len();
+            block[pos..pos + 2].copy_from_slice(&(value.len() as u16).to_le_bytes());
+            pos += 2;
+            block[pos..pos + value.len()].copy_from_slice(value);
+            pos += value.len();
+        }
+        let used = (pos - 2) as u16;
+        block[0..2].copy_from_slice(&used.to_le_bytes());
+
+        let block_id = match self.read_disk_inode(|disk_inode| disk_inode.xattr_block_id) {
+            Some(id) if id != 0 => id,
+            _ => {
+                let id = fs.alloc_data();
+                self.modify_disk_inode(|disk_inode| disk_inode.xattr_block_id = id);
+                id
+            }
+        };
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |dst: &mut [u8; BLOCK_SZ]| *dst = block);
+        Ok(())
+    }
+}
+
+/// Parse `(name_len: u8, name, value_len: u16, value)` records packed
+/// after a 2-byte `used`-length header out of an xattr block (see
+/// [`Inode::write_xattr_block`]), calling `f` with each `(name, value)`
+/// pair in order.
+fn for_each_xattr(block: &[u8; BLOCK_SZ], mut f: impl FnMut(&str, &[u8])) {
+    let used = u16::from_le_bytes([block[0], block[1]]) as usize;
+    let mut pos = 2;
+    while pos < 2 + used {
+        let name_len = block[pos] as usize;
+        pos += 1;
+        let name = core::str::from_utf8(&block[pos..pos + name_len]).unwrap_or("");
+        pos += name_len;
+        let value_len = u16::from_le_bytes([block[pos], block[pos + 1]]) as usize;
+        pos += 2;
+        let value = &block[pos..pos + value_len];
+        pos += value_len;
+        f(name, value);
+    }
 }
 
 bitflags! {
@@ -332,9 +997,53 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// symbolic link (see `Inode::symlink`)
+        const LINK  = 0o120000;
     }
 }
 
+bitflags! {
+    /// Requested access, as tested by [`Inode::check_access`]
+    pub struct AccessMode: u8 {
+        /// readable
+        const READ  = 0b100;
+        /// writable
+        const WRITE = 0b010;
+        /// executable (or, for a directory, searchable)
+        const EXEC  = 0b001;
+    }
+}
+
+bitflags! {
+    /// Flags for [`Inode::rename`]
+    pub struct RenameFlags: u32 {
+        /// fail instead of replacing the target if it already exists
+        const NOREPLACE = 1 << 0;
+        /// atomically swap the source and target entries instead of
+        /// replacing; both must already exist
+        const EXCHANGE  = 1 << 1;
+    }
+}
+
+/// Overall filesystem usage, as reported by [`Inode::stat_fs`]. Driven by
+/// `EasyFileSystem`'s inode/data bitmaps (`total_inodes`/`used_inodes` and
+/// `total_data_blocks`/`used_data_blocks`), so computing it never needs to
+/// scan a single data block.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct FsStat {
+    /// size of a block, in bytes
+    pub block_size: u32,
+    /// total number of data blocks
+    pub total_blocks: u64,
+    /// number of data blocks not currently allocated
+    pub free_blocks: u64,
+    /// total number of inodes the filesystem can hold
+    pub total_inodes: u64,
+    /// number of inodes not currently allocated
+    pub free_inodes: u64,
+}
+
 /// The state of a inode(file)
 #[repr(C)]
 #[derive(Debug)]
@@ -347,8 +1056,20 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// owning user id
+    pub uid: u32,
+    /// owning group id
+    pub gid: u32,
+    /// permission bits (rwxrwxrwx plus setuid/setgid/sticky)
+    pub perm: u16,
+    /// time of last access, stamped by `Inode::read_at` (see `set_clock`)
+    pub atime: u64,
+    /// time of last data modification, stamped by `write_at`/`clear`
+    pub mtime: u64,
+    /// time of last metadata change, stamped by `write_at`/`clear`/`link`/`unlink`
+    pub ctime: u64,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u64; 3],
 }
 
 impl Default for Stat {
@@ -358,6 +1079,12 @@ impl Default for Stat {
             ino: Default::default(),
             mode: StatMode::NULL,
             nlink: Default::default(),
+            uid: Default::default(),
+            gid: Default::default(),
+            perm: Default::default(),
+            atime: Default::default(),
+            mtime: Default::default(),
+            ctime: Default::default(),
             pad: Default::default(),
         }
     }
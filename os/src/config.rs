@@ -27,6 +27,11 @@ pub const MEMORY_END: usize = 0x88000000;
 /// big stride for stride scheduling algorithm
 pub const BIG_STRIDE: usize = 100_000;
 
+/// Maximum number of harts (CPU cores) the kernel boots on. Bounds the
+/// per-hart `Processor` array in `task::processor`; QEMU's `virt` machine is
+/// usually started with far fewer than this.
+pub const MAX_HART_NUM: usize = 8;
+
 // 内存映射 I/O (MMIO, Memory-Mapped I/O) 指的是外设的设备寄存器可以通过特定的物理内存地址来访问，
 // 每个外设的设备寄存器都分布在没有交集的一个或数个物理地址区间中，不同外设的设备寄存器所占的物理地址空间也不会产生交集，
 // 且这些外设物理地址区间也不会和RAM的物理内存所在的区间存在交集（注：在后续的外设相关章节有更深入的讲解）。
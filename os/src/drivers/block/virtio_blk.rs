@@ -3,7 +3,7 @@ use crate::mm::{
     frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
     StepByOne, VirtAddr,
 };
-use crate::sync::UPSafeCell;
+use crate::sync::SpinLock;
 use alloc::vec::Vec;
 use lazy_static::*;
 use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
@@ -20,11 +20,12 @@ const VIRTIO0: usize = 0x10001000;
 ///
 /// 我们将 virtio-drivers crate 提供的 VirtIO 块设备抽象 VirtIOBlk 包装为我们自己的
 /// VirtIOBlock，实质上只是加上了一层互斥锁，生成一个新的类型来实现 easy-fs 需要的
-/// BlockDevice Trait
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+/// BlockDevice Trait。任何一个 hart 都可能发起块设备 I/O，所以这把锁必须是真正
+/// 跨核互斥的 `SpinLock`，而不是只在单核下成立的 `UPSafeCell`。
+pub struct VirtIOBlock(SpinLock<VirtIOBlk<'static, VirtioHal>>);
 
 lazy_static! {
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
+    static ref QUEUE_FRAMES: SpinLock<Vec<FrameTracker>> = SpinLock::new(Vec::new());
 }
 
 impl BlockDevice for VirtIOBlock {
@@ -47,7 +48,7 @@ impl VirtIOBlock {
     /// Create a new VirtIOBlock driver with VIRTIO0 base_addr for virtio_blk device
     pub fn new() -> Self {
         unsafe {
-            Self(UPSafeCell::new(
+            Self(SpinLock::new(
                 VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
             ))
         }
@@ -16,6 +16,28 @@ pub trait File: Send + Sync {
     fn write(&self, buf: UserBuffer) -> usize;
     /// get stat of the file
     fn stat(&self, st: &mut Stat);
+
+    /// Random-access read at a byte `offset`, independent of the file's own
+    /// cursor, filling as much of `buf` as the file has data for and
+    /// zero-filling the rest. Used by `mmap`'s page-fault handler to pull
+    /// in one page of a file-backed mapping at a time; `OSInode` should
+    /// override this with a `get_block_cache`-backed implementation.
+    /// Returns the number of bytes actually read from the file (before
+    /// zero-fill). The default implementation supports no file and always
+    /// returns a zero page, which is enough for anonymous-like callers but
+    /// not for mapping real files.
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> usize {
+        buf.fill(0);
+        0
+    }
+
+    /// Random-access write at a byte `offset`, the write-back counterpart
+    /// of [`Self::read_at`], used when flushing a dirty shared file-backed
+    /// mapping back to disk on `munmap`. The default implementation
+    /// discards the write.
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
 }
 
 use easy_fs::Stat;
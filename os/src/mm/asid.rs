@@ -0,0 +1,84 @@
+//! Address-Space-ID (ASID) allocation
+//!
+//! SV39's `satp` register reserves bits `[59:44]` for a 16-bit ASID that tags
+//! TLB entries with the address space they belong to, so switching between a
+//! small set of processes doesn't require flushing the whole TLB on every
+//! context switch. Real hardware is only required to implement *some* prefix
+//! of those bits, so the allocator is built around a configurable maximum and
+//! the "asid 0" fallback below switches a [`super::MemorySet`] back to a
+//! global flush once the pool runs out.
+use crate::sync::SpinLock;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Number of ASID bits SV39 reserves in `satp`
+pub const ASID_BITS: usize = 16;
+
+/// asid 0 is reserved: it is never handed out by [`AsidAllocator::alloc`] and
+/// means "no ASID tagging, fall back to a global `sfence.vma`". It is used by
+/// the kernel's own address space and by any [`super::MemorySet`] created
+/// after the pool is exhausted.
+pub const GLOBAL_ASID: usize = 0;
+
+struct AsidAllocator {
+    next: usize,
+    max: usize,
+    recycled: Vec<usize>,
+}
+
+impl AsidAllocator {
+    fn new(max: usize) -> Self {
+        Self {
+            next: GLOBAL_ASID + 1,
+            max,
+            recycled: Vec::new(),
+        }
+    }
+
+    /// Allocate a fresh ASID, or `None` if the pool is exhausted.
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(asid) = self.recycled.pop() {
+            return Some(asid);
+        }
+        if self.next >= self.max {
+            return None;
+        }
+        let asid = self.next;
+        self.next += 1;
+        Some(asid)
+    }
+
+    fn dealloc(&mut self, asid: usize) {
+        if asid == GLOBAL_ASID {
+            return;
+        }
+        debug_assert!(
+            !self.recycled.contains(&asid),
+            "asid {} deallocated twice",
+            asid
+        );
+        self.recycled.push(asid);
+    }
+}
+
+lazy_static! {
+    /// The global ASID allocator. Assumes the full 16-bit space is usable;
+    /// platforms implementing fewer bits can lower `max` here.
+    ///
+    /// Any hart can fork/spawn a task and allocate an ASID at the same
+    /// time, so this needs real mutual exclusion rather than `UPSafeCell`'s
+    /// single-hart promise.
+    static ref ASID_ALLOCATOR: SpinLock<AsidAllocator> =
+        SpinLock::new(AsidAllocator::new(1 << ASID_BITS));
+}
+
+/// Allocate an ASID for a new address space, or [`GLOBAL_ASID`] if the pool
+/// is exhausted (the caller then falls back to a global TLB flush on switch).
+pub fn asid_alloc() -> usize {
+    ASID_ALLOCATOR.exclusive_access().alloc().unwrap_or(GLOBAL_ASID)
+}
+
+/// Return an ASID to the pool. A no-op for [`GLOBAL_ASID`].
+pub fn asid_dealloc(asid: usize) {
+    ASID_ALLOCATOR.exclusive_access().dealloc(asid);
+}
@@ -1,10 +1,15 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
-use super::{alloc_check, alloc_mm, dealloc_check, dealloc_mm, frame_alloc, FrameTracker};
+use super::asid::{asid_alloc, asid_dealloc, ASID_BITS, GLOBAL_ASID};
+use super::{
+    alloc_check, alloc_mm, alloc_mm_file, alloc_mm_huge, dealloc_check, dealloc_mm, frame_alloc,
+    protect_mm, FrameTracker,
+};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
 use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
-use crate::sync::UPSafeCell;
+use crate::fs::File;
+use crate::sync::SpinLock;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -27,8 +32,11 @@ extern "C" {
 
 lazy_static! {
     /// The kernel's initial memory mapping(kernel address space)
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    ///
+    /// Every hart maps into this same address space, so it needs real
+    /// mutual exclusion rather than `UPSafeCell`'s single-hart promise.
+    pub static ref KERNEL_SPACE: Arc<SpinLock<MemorySet>> =
+        Arc::new(SpinLock::new(MemorySet::new_kernel()));
 }
 
 /// the kernel token
@@ -40,12 +48,21 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// Address-Space-ID tagging this address space's TLB entries.
+    /// [`GLOBAL_ASID`] means "not tagged" (the kernel space, or any user
+    /// space created after the ASID pool ran out) and falls back to a
+    /// global `sfence.vma` on activation instead of an ASID-scoped one.
+    asid: usize,
 }
 // [liuzl 2024年10月26日14:47:58]
 // 对于上面的这个areas，本来想要做一个整合操作的，也就是说相邻的两个MapArea整合成一个
 // MapArea，但是感觉从逻辑上有点问题，因为首先合并区间的算法主要的作用是合并重叠的区间
 // 但是在这里MapArea和MapArea之间根本不可能发生重叠，最多相邻，就算是相邻的话，可能这几个
 // MapArea都有不同的MapPermission或者MapType，实在不好进行区分和合并
+//
+// 后续更新：上面的顾虑本身没错，但只要把"合并"限定在 map_type/map_perm（以及
+// lazy 状态）完全相同的相邻 Framed 区域上，区分就是平凡的——见 `push` 里调用的
+// `coalesce_adjacent`。
 
 impl MemorySet {
     /// Create a new empty `MemorySet`.
@@ -53,23 +70,69 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            asid: GLOBAL_ASID,
         }
     }
-    /// Get the page table token
+    /// Get the page table token, with this address space's ASID folded into
+    /// `satp`'s `ASID[59:44]` field.
     pub fn token(&self) -> usize {
-        self.page_table.token()
+        let base = self.page_table.token();
+        if self.asid == GLOBAL_ASID {
+            base
+        } else {
+            let asid_mask = ((1usize << ASID_BITS) - 1) << 44;
+            (base & !asid_mask) | (self.asid << 44)
+        }
     }
     /// Assume that no conflicts.
+    ///
+    /// `populate` mirrors `mmap`'s `MAP_POPULATE`: when `true` every page is
+    /// allocated and mapped right away (the old, eager behaviour); when
+    /// `false` the area is recorded with no frames and no PTEs installed,
+    /// and each page is instead faulted in on first access by
+    /// [`Self::handle_lazy_fault`].
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+        populate: bool,
+    ) -> Result<(), &'static str> {
+        let area = if populate {
+            MapArea::new(start_va, end_va, MapType::Framed, permission)
+        } else {
+            MapArea::new_lazy_framed(start_va, end_va, permission)
+        };
+        self.push(area, None)
+    }
+    /// `MAP_HUGETLB`-style variant of [`Self::insert_framed_area`]: backs
+    /// `[start_va, end_va)` with 2 MiB megapages instead of 4 KiB pages.
+    /// Always eager (there is no lazy-huge-page path). See
+    /// [`MapArea::new_huge_framed`] for the alignment/size requirement.
+    pub fn insert_huge_framed_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> Result<(), &'static str> {
+        let area = MapArea::new_huge_framed(start_va, end_va, permission)?;
+        self.push(area, None)
+    }
+    /// Map `file`, starting at `file_offset` bytes into it, over
+    /// `[start_va, end_va)`. No page is actually read or allocated until it
+    /// faults in through [`Self::handle_mmap_fault`].
+    pub fn insert_file_backed_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        file: Arc<dyn File + Send + Sync>,
+        file_offset: usize,
+    ) -> Result<(), &'static str> {
         self.push(
-            MapArea::new(start_va, end_va, MapType::Framed, permission),
+            MapArea::new_file_backed(start_va, end_va, permission, file, file_offset),
             None,
-        );
+        )
     }
     /// remove a area
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
@@ -86,14 +149,55 @@ impl MemorySet {
     /// Add a new MapArea into this MemorySet.
     /// Assuming that there are no conflicts in the virtual address
     /// space.
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    ///
+    /// Fails without leaving anything behind if physical memory runs out
+    /// partway through: `MapArea::map` already unwinds its own partial work,
+    /// and a failed `copy_data` is followed by unmapping the whole area, so
+    /// on `Err` `map_area` is dropped unmapped.
+    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> Result<(), &'static str> {
+        map_area.map(&mut self.page_table)?;
         if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data);
+            if let Err(e) = map_area.copy_data(&mut self.page_table, data) {
+                map_area.unmap(&mut self.page_table);
+                return Err(e);
+            }
         }
         self.areas.push(map_area);
         self.areas
             .sort_by_key(|map_area| map_area.vpn_range.get_start());
+        self.coalesce_adjacent();
+        Ok(())
+    }
+    /// Merge adjacent `Framed` areas that are otherwise identical
+    /// (`map_perm` and lazy-ness both match) into one, draining the second
+    /// area's `data_frames` into the first — the two maps are keyed by VPN
+    /// and the ranges are disjoint and contiguous, so they concatenate
+    /// cleanly. Keeps `areas` shorter, which is what `is_conflict`,
+    /// `is_vmm_fully_mapped`, and `free`'s `CrossType::Multiple` traversal
+    /// all linearly scan. Assumes `areas` is already sorted by start vpn.
+    fn coalesce_adjacent(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.areas.len() {
+            let mergeable = {
+                let a = &self.areas[i];
+                let b = &self.areas[i + 1];
+                a.map_type == MapType::Framed
+                    && b.map_type == MapType::Framed
+                    && a.map_perm == b.map_perm
+                    && a.lazy == b.lazy
+                    && !a.huge
+                    && !b.huge
+                    && a.vpn_range.get_end() == b.vpn_range.get_start()
+            };
+            if mergeable {
+                let b = self.areas.remove(i + 1);
+                let a = &mut self.areas[i];
+                a.vpn_range = VPNRange::new(a.vpn_range.get_start(), b.vpn_range.get_end());
+                a.data_frames.extend(b.data_frames);
+            } else {
+                i += 1;
+            }
+        }
     }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
@@ -116,75 +220,96 @@ impl MemorySet {
             ".bss [{:#x}, {:#x})",
             sbss_with_stack as usize, ebss as usize
         );
+        // Identical areas never allocate a frame (map_one's Identical branch
+        // is `ppn = PhysPageNum(vpn.0)`), so push() can't actually fail here.
+        const UNREACHABLE: &str = "identical-mapped kernel area can't run out of memory";
         info!("mapping .text section");
-        memory_set.push(
-            MapArea::new(
-                (stext as usize).into(),
-                (etext as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::X,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (stext as usize).into(),
+                    (etext as usize).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::X,
+                ),
+                None,
+            )
+            .expect(UNREACHABLE);
         info!("mapping .rodata section");
-        memory_set.push(
-            MapArea::new(
-                (srodata as usize).into(),
-                (erodata as usize).into(),
-                MapType::Identical,
-                MapPermission::R,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (srodata as usize).into(),
+                    (erodata as usize).into(),
+                    MapType::Identical,
+                    MapPermission::R,
+                ),
+                None,
+            )
+            .expect(UNREACHABLE);
         info!("mapping .data section");
-        memory_set.push(
-            MapArea::new(
-                (sdata as usize).into(),
-                (edata as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (sdata as usize).into(),
+                    (edata as usize).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )
+            .expect(UNREACHABLE);
         info!("mapping .bss section");
-        memory_set.push(
-            MapArea::new(
-                (sbss_with_stack as usize).into(),
-                (ebss as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (sbss_with_stack as usize).into(),
+                    (ebss as usize).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )
+            .expect(UNREACHABLE);
         info!("mapping physical memory");
-        memory_set.push(
-            MapArea::new(
-                (ekernel as usize).into(),
-                MEMORY_END.into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
-        // 在创建内核地址空间的时候需要建立页表映射
-        info!("mapping memory-mapped registers");
-        for pair in MMIO {
-            memory_set.push(
+        memory_set
+            .push(
                 MapArea::new(
-                    (*pair).0.into(),
-                    ((*pair).0 + (*pair).1).into(),
+                    (ekernel as usize).into(),
+                    MEMORY_END.into(),
                     MapType::Identical,
                     MapPermission::R | MapPermission::W,
                 ),
                 None,
-            );
+            )
+            .expect(UNREACHABLE);
+        // 在创建内核地址空间的时候需要建立页表映射
+        info!("mapping memory-mapped registers");
+        for pair in MMIO {
+            memory_set
+                .push(
+                    MapArea::new(
+                        (*pair).0.into(),
+                        ((*pair).0 + (*pair).1).into(),
+                        MapType::Identical,
+                        MapPermission::R | MapPermission::W,
+                    ),
+                    None,
+                )
+                .expect(UNREACHABLE);
         }
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
     /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    ///
+    /// Fails with `Err` instead of panicking if physical memory runs out
+    /// partway through, e.g. for an over-large binary — the caller (an
+    /// `exec`/`spawn`) can then reject the load instead of taking down the
+    /// kernel.
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, usize, usize), &'static str> {
         let mut memory_set = Self::new_bare();
+        memory_set.asid = asid_alloc();
         // map trampoline
         memory_set.map_trampoline();
         // map program headers of elf, with U flag
@@ -215,7 +340,7 @@ impl MemorySet {
                 memory_set.push(
                     map_area,
                     Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                )?;
             }
         }
         // map user stack with U flags
@@ -232,7 +357,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+        )?;
         // used in sbrk
         memory_set.push(
             MapArea::new(
@@ -242,7 +367,7 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+        )?;
         // map TrapContext
         memory_set.push(
             MapArea::new(
@@ -252,40 +377,198 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        (
+        )?;
+        Ok((
             memory_set,
             user_stack_top,
             elf.header.pt2.entry_point() as usize,
-        )
+        ))
     }
     /// Create a new address space by copy code&data from a exited process's address space.
-    pub fn from_existed_user(user_space: &Self) -> Self {
+    ///
+    /// Copy-on-write: writable framed areas are shared with `user_space`
+    /// (frame refcount bumped, both page tables downgraded to read-only)
+    /// instead of being deep-copied; a write to such a page later faults
+    /// into [`MemorySet::handle_cow_fault`]. Takes `user_space` by `&mut`
+    /// because the parent's page table also needs its writable mappings
+    /// downgraded to read-only.
+    ///
+    /// Fails if physical memory runs out while deep-copying a non-COW area
+    /// (e.g. an identically-mapped or read-only area); `user_space` is left
+    /// with whatever COW downgrades already happened, which is harmless
+    /// since those areas are unaffected by the failure.
+    pub fn from_existed_user(user_space: &mut Self) -> Result<Self, &'static str> {
         let mut memory_set = Self::new_bare();
+        memory_set.asid = asid_alloc();
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
-        for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+        for area in user_space.areas.iter_mut() {
+            let mut new_area = MapArea::from_another(area);
+            if area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::W) {
+                // COW: share every frame read-only in both address spaces.
+                let cow_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap() & !PTEFlags::W;
+                for vpn in area.vpn_range {
+                    let frame = Arc::clone(area.data_frames.get(&vpn).unwrap());
+                    let ppn = frame.ppn;
+                    user_space.page_table.unmap(vpn);
+                    user_space.page_table.map(vpn, ppn, cow_flags);
+                    memory_set.page_table.map(vpn, ppn, cow_flags);
+                    new_area.data_frames.insert(vpn, frame);
+                }
+                memory_set.areas.push(new_area);
+            } else {
+                memory_set.push(new_area, None)?;
+                // not a COW candidate (read-only, or identically mapped): deep copy
+                for vpn in area.vpn_range {
+                    let src_ppn = user_space
+                        .translate(vpn)
+                        .ok_or("from_existed_user: source page not mapped")?
+                        .ppn();
+                    let dst_ppn = memory_set
+                        .translate(vpn)
+                        .ok_or("from_existed_user: destination page not mapped")?
+                        .ppn();
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
             }
         }
         memory_set
+            .areas
+            .sort_by_key(|map_area| map_area.vpn_range.get_start());
+        // The parent keeps running on this hart the moment fork() returns,
+        // and its TLB may still cache the writable translation for any VPN
+        // just downgraded above; without this it could bypass
+        // `handle_cow_fault` entirely and write straight into a frame now
+        // shared with the child. Same pattern as `Self::protect`.
+        user_space.sfence_vma_local();
+        Ok(memory_set)
+    }
+
+    /// Handle a store/instruction page fault on `vpn`.
+    ///
+    /// If `vpn` belongs to a COW page (a writable framed area currently
+    /// mapped without the `W` bit), give this address space its own frame —
+    /// copying the contents if it was still shared — and restore full
+    /// permission, then return `true` so the caller can resume the faulting
+    /// instruction. Returns `false` if `vpn` is not a COW page, meaning the
+    /// fault is a genuine access violation.
+    ///
+    /// The store-page-fault trap handler must call this first and only
+    /// treat the fault as fatal if it returns `false`.
+    pub fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let area_idx = self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end());
+        let area_idx = match area_idx {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let area = &mut self.areas[area_idx];
+        if area.map_type != MapType::Framed || !area.map_perm.contains(MapPermission::W) {
+            return false;
+        }
+        let frame = match area.data_frames.get(&vpn) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        if Arc::strong_count(frame) == 1 {
+            // sole owner already: just restore the writable bit
+            let ppn = frame.ppn;
+            self.page_table.unmap(vpn);
+            self.page_table.map(vpn, ppn, flags);
+        } else {
+            // still shared: split off a private, writable copy
+            let mut new_frame = frame_alloc().unwrap();
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            let new_ppn = new_frame.ppn;
+            area.data_frames.insert(vpn, Arc::new(new_frame));
+            self.page_table.unmap(vpn);
+            self.page_table.map(vpn, new_ppn, flags);
+        }
+        true
+    }
+    /// Handle a load/store page fault on `vpn` inside a lazily-mapped
+    /// anonymous area (an `mmap` without `MAP_POPULATE`, see [`mmap`]):
+    /// allocate and zero a single frame, map it with the area's
+    /// permissions, and return so the faulting instruction can retry.
+    /// Returns `Err(())` if `vpn` doesn't belong to such an area (it's
+    /// already been faulted in, or belongs to a different kind of area
+    /// entirely — a genuine access violation, or one for
+    /// [`Self::handle_cow_fault`]/[`Self::handle_mmap_fault`] instead).
+    pub fn handle_lazy_fault(&mut self, vpn: VirtPageNum) -> Result<(), ()> {
+        let area_idx = self.areas.iter().position(|area| {
+            area.map_type == MapType::Framed
+                && area.lazy
+                && area.vpn_range.get_start() <= vpn
+                && vpn < area.vpn_range.get_end()
+        });
+        let area_idx = area_idx.ok_or(())?;
+        let area = &mut self.areas[area_idx];
+        if area.data_frames.contains_key(&vpn) {
+            // already faulted in: a repeat fault here is a real violation
+            return Err(());
+        }
+        let frame = frame_alloc().unwrap();
+        let ppn = frame.ppn;
+        ppn.get_bytes_array().fill(0);
+        area.data_frames.insert(vpn, Arc::new(frame));
+        let pte_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        self.page_table.map(vpn, ppn, pte_flags);
+        Ok(())
+    }
+    /// Handle a page fault on `vpn` inside a file-backed mmap area: allocate
+    /// a frame, read the matching chunk of the backing file into it (via
+    /// [`File::read_at`], zero-filling past EOF), and map it with the
+    /// area's permissions. Returns `false` if `vpn` doesn't belong to a
+    /// `MapType::FileBacked` area, meaning the fault is a genuine access
+    /// violation (or belongs to `handle_cow_fault` instead).
+    pub fn handle_mmap_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let area_idx = self.areas.iter().position(|area| {
+            area.map_type == MapType::FileBacked
+                && area.vpn_range.get_start() <= vpn
+                && vpn < area.vpn_range.get_end()
+        });
+        let area_idx = match area_idx {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let area = &mut self.areas[area_idx];
+        let backing = match &area.file_backing {
+            Some(backing) => backing.clone(),
+            None => return false,
+        };
+        let page_index = vpn.0 - area.vpn_range.get_start().0;
+        let file_offset = backing.offset + page_index * PAGE_SIZE;
+
+        let frame = frame_alloc().unwrap();
+        let ppn = frame.ppn;
+        backing.file.read_at(file_offset, ppn.get_bytes_array());
+        area.data_frames.insert(vpn, Arc::new(frame));
+
+        let pte_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        self.page_table.map(vpn, ppn, pte_flags);
+        true
     }
     /// Change page table by writing satp CSR Register.
+    ///
+    /// Flushes only this address space's TLB entries (`sfence.vma` scoped to
+    /// its ASID) instead of the whole TLB, unless it has no ASID of its own
+    /// (the kernel space, or a user space created once the ASID pool was
+    /// exhausted), in which case it falls back to a global flush.
     pub fn activate(&self) {
-        let satp = self.page_table.token();
+        let satp = self.token();
         unsafe {
             satp::write(satp);
-            asm!("sfence.vma");
         }
+        self.sfence_vma_local();
     }
     /// Translate a virtual page number to a page table entry
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
@@ -320,13 +603,98 @@ impl MemorySet {
             .iter_mut()
             .find(|area| area.vpn_range.get_start() == start.floor())
         {
-            area.append_to(&mut self.page_table, new_end.ceil());
-            true
+            area.append_to(&mut self.page_table, new_end.ceil())
         } else {
             false
         }
     }
 
+    /// Change the `MapPermission` of every page in `[start, end)` to
+    /// `new_perm`, splitting whichever `MapArea`(s) only partially overlap
+    /// the range into an unchanged prefix, a reflagged middle, and an
+    /// unchanged suffix, so each resulting `MapArea` keeps a single uniform
+    /// `map_perm`. Used by `sys_mprotect`.
+    ///
+    /// Not megapage-aware: rewriting a huge-backed area's single level-1
+    /// leaf one 4 KiB `set_perm` call at a time would corrupt it, since
+    /// that's not how a megapage PTE works. Rejected up front instead:
+    /// returns `Err` without touching any area if `[start, end)` overlaps a
+    /// `MAP_HUGETLB` region.
+    pub fn protect(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        new_perm: MapPermission,
+    ) -> Result<(), &'static str> {
+        if self.areas.iter().any(|area| {
+            let area_start = area.vpn_range.get_start();
+            let area_end = area.vpn_range.get_end();
+            area.huge && area_start < end && start < area_end
+        }) {
+            return Err("mprotect on a MAP_HUGETLB region is not supported");
+        }
+
+        let mut i = 0;
+        while i < self.areas.len() {
+            let area_start = self.areas[i].vpn_range.get_start();
+            let area_end = self.areas[i].vpn_range.get_end();
+            if area_end <= start || end <= area_start {
+                // no overlap with the requested range
+                i += 1;
+                continue;
+            }
+            let clip_start = area_start.max(start);
+            let clip_end = area_end.min(end);
+
+            if clip_end < area_end {
+                // split off an unchanged suffix
+                let mut suffix = MapArea::from_another(&self.areas[i]);
+                suffix.vpn_range = VPNRange::new(clip_end, area_end);
+                for vpn in suffix.vpn_range {
+                    if let Some(frame) = self.areas[i].data_frames.remove(&vpn) {
+                        suffix.data_frames.insert(vpn, frame);
+                    }
+                }
+                self.areas.insert(i + 1, suffix);
+            }
+            if area_start < clip_start {
+                // split off an unchanged prefix
+                let mut prefix = MapArea::from_another(&self.areas[i]);
+                prefix.vpn_range = VPNRange::new(area_start, clip_start);
+                for vpn in prefix.vpn_range {
+                    if let Some(frame) = self.areas[i].data_frames.remove(&vpn) {
+                        prefix.data_frames.insert(vpn, frame);
+                    }
+                }
+                self.areas[i].vpn_range = VPNRange::new(clip_start, clip_end);
+                self.areas.insert(i, prefix);
+                i += 1; // self.areas[i] is now the reflagged middle
+            } else {
+                self.areas[i].vpn_range = VPNRange::new(clip_start, clip_end);
+            }
+
+            self.areas[i].set_perm(&mut self.page_table, new_perm);
+            i += 1;
+        }
+        self.areas
+            .sort_by_key(|map_area| map_area.vpn_range.get_start());
+        self.sfence_vma_local();
+        Ok(())
+    }
+
+    /// Flush this address space's TLB entries, the same way [`Self::activate`]
+    /// does, without re-writing `satp` (used after rewriting PTEs in place,
+    /// e.g. by [`Self::protect`]).
+    fn sfence_vma_local(&self) {
+        unsafe {
+            if self.asid == GLOBAL_ASID {
+                asm!("sfence.vma");
+            } else {
+                asm!("sfence.vma zero, {asid}", asid = in(reg) self.asid);
+            }
+        }
+    }
+
     /// detect whether a range ordered by user is conflict with assigned virtual memory
     pub fn is_conflict(&self, start: VirtPageNum, end: VirtPageNum) -> Result<(), &'static str> {
         for map_area in &self.areas {
@@ -393,6 +761,46 @@ impl MemorySet {
         Err("vmm range does't fully mapped!")
     }
 
+    /// Verify that every page in `[start, start+len)` is mapped, accessible
+    /// from user mode, and carries at least the permissions in `want` (some
+    /// combination of `R`/`W`/`X`; the `U` bit is always required whether or
+    /// not it's set in `want`). Reuses [`Self::is_vmm_fully_mapped`]'s
+    /// cross-area traversal so a range straddling an unmapped gap between
+    /// two `MapArea`s is rejected exactly like `munmap`/`mprotect` already
+    /// reject it, not just a range that falls fully outside every area.
+    ///
+    /// Gives syscall implementations a single guard to call before reading
+    /// or writing through a user-supplied pointer, instead of trusting it.
+    pub fn check_user_access(
+        &self,
+        start: VirtAddr,
+        len: usize,
+        want: MapPermission,
+    ) -> Result<(), &'static str> {
+        let end = start
+            .0
+            .checked_add(len)
+            .ok_or("user buffer range overflows the address space")?;
+        let start_vpn = start.floor();
+        let end_vpn = VirtAddr::from(end).ceil();
+        if start_vpn == end_vpn {
+            return Ok(());
+        }
+        self.is_vmm_fully_mapped(start_vpn, end_vpn)?;
+
+        let want_flags = PTEFlags::from_bits(want.bits).unwrap() | PTEFlags::U;
+        for vpn in VPNRange::new(start_vpn, end_vpn) {
+            let pte = self
+                .translate(vpn)
+                .filter(|pte| pte.is_valid())
+                .ok_or("user buffer range straddles an unmapped page")?;
+            if !pte.flags().contains(want_flags) {
+                return Err("user buffer range is missing a required permission bit");
+            }
+        }
+        Ok(())
+    }
+
     /// free mememory from start_va to end_va
     pub fn free(&mut self, start: VirtPageNum, end: VirtPageNum, cross_type: CrossType) {
         println!("[kernel] cross type = {:?}", cross_type);
@@ -419,9 +827,100 @@ impl MemorySet {
     }
 }
 
+impl Drop for MemorySet {
+    /// Hand the ASID back to the pool, e.g. when `exec` replaces a task's
+    /// `memory_set`, or when a zombie's `TaskControlBlock` (and its
+    /// `memory_set`) is finally dropped after its parent reaps it in
+    /// `sys_waitpid`.
+    fn drop(&mut self) {
+        asid_dealloc(self.asid);
+    }
+}
+
 /// mmap systemcall implication
+///
+/// `populate` is `mmap`'s `MAP_POPULATE`: when `true` every page is
+/// allocated and mapped eagerly, same as before; when `false` the mapping
+/// is anonymous and lazy — pages are left unmapped and faulted in one at a
+/// time by [`MemorySet::handle_lazy_fault`] as the task touches them.
+///
+/// `huge` is `MAP_HUGETLB`: back the range with 2 MiB megapages instead
+/// (see [`MemorySet::insert_huge_framed_area`]); `populate` is ignored in
+/// that case since there is no lazy-huge-page path.
+#[allow(unused)]
+pub fn mmap(
+    start: usize,
+    len: usize,
+    port: usize,
+    populate: bool,
+    huge: bool,
+) -> Result<(), &'static str> {
+    let vpn_range = alloc_check(start, start + len, port)?;
+    let mut map_perm = MapPermission::U;
+    if port & 0x1 == 0x1 {
+        map_perm |= MapPermission::R;
+    }
+    if port & 0x2 == 0x2 {
+        map_perm |= MapPermission::W;
+    }
+    if port & 0x4 == 0x4 {
+        map_perm |= MapPermission::X;
+    }
+
+    if huge {
+        alloc_mm_huge(vpn_range.0, vpn_range.1, map_perm)?;
+    } else {
+        alloc_mm(vpn_range.0, vpn_range.1, map_perm, populate)?; // 这里绝逼有竞态条件的问题，看来rCore操作系统是真的简陋
+    }
+
+    Ok(())
+}
+
+/// mprotect syscall implementation: change the `port` permission bits of
+/// an already-mapped `[start, start+len)`, splitting the covering
+/// `MapArea`(s) as needed (see [`MemorySet::protect`]). Unlike `mmap`, the
+/// whole range must already be mapped.
 #[allow(unused)]
-pub fn mmap(start: usize, len: usize, port: usize) -> Result<(), &'static str> {
+pub fn mprotect(start: usize, len: usize, port: usize) -> Result<(), &'static str> {
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() {
+        return Err("start address must be page aligned");
+    }
+    if port & !0x7 != 0 {
+        return Err(
+            "port: Bit 0 indicates whether it is readable, bit 1 indicates whether it is writable, \
+                and bit 2 indicates whether it is executable. Other bits are invalid and must be 0."
+        );
+    }
+
+    let start_vpn = start_va.floor();
+    let end_vpn = VirtAddr::from(start + len).ceil();
+
+    let mut map_perm = MapPermission::U;
+    if port & 0x1 == 0x1 {
+        map_perm |= MapPermission::R;
+    }
+    if port & 0x2 == 0x2 {
+        map_perm |= MapPermission::W;
+    }
+    if port & 0x4 == 0x4 {
+        map_perm |= MapPermission::X;
+    }
+
+    protect_mm(start_vpn, end_vpn, map_perm)
+}
+
+/// mmap systemcall implementation for a file-backed mapping: same
+/// permission/overlap checks as [`mmap`], but the pages are demand-paged in
+/// from `file` (starting at `file_offset`) instead of being anonymous.
+#[allow(unused)]
+pub fn mmap_file(
+    start: usize,
+    len: usize,
+    port: usize,
+    file: Arc<dyn File + Send + Sync>,
+    file_offset: usize,
+) -> Result<(), &'static str> {
     let vpn_range = alloc_check(start, start + len, port)?;
     let mut map_perm = MapPermission::U;
     if port & 0x1 == 0x1 {
@@ -434,7 +933,7 @@ pub fn mmap(start: usize, len: usize, port: usize) -> Result<(), &'static str> {
         map_perm |= MapPermission::X;
     }
 
-    alloc_mm(vpn_range.0, vpn_range.1, map_perm); // 这里绝逼有竞态条件的问题，看来rCore操作系统是真的简陋
+    alloc_mm_file(vpn_range.0, vpn_range.1, map_perm, file, file_offset)?;
 
     Ok(())
 }
@@ -460,14 +959,39 @@ pub fn munmap(start: usize, len: usize) -> Result<(), &'static str> {
     Ok(())
 }
 
+/// A file and the byte offset within it that backs a [`MapType::FileBacked`]
+/// `MapArea`'s first page.
+#[derive(Clone)]
+pub struct FileBacking {
+    file: Arc<dyn File + Send + Sync>,
+    offset: usize,
+}
+
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    // Wrapped in `Arc` so a copy-on-write fork can share a frame between the
+    // parent's and child's `MapArea` until one of them actually writes to it.
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// Set for `MapType::FileBacked` areas; `None` otherwise.
+    file_backing: Option<FileBacking>,
+    /// Set for a `MapType::Framed` area created without `MAP_POPULATE`: no
+    /// frame is allocated and no PTE installed until
+    /// [`MemorySet::handle_lazy_fault`] services the first access to each
+    /// page. Always `false` for every other `map_type`.
+    lazy: bool,
+    /// Set for a `MapType::Framed` area backed by 2 MiB megapages instead of
+    /// ordinary 4 KiB pages — see [`MapArea::new_huge_framed`]. Mutually
+    /// exclusive with `lazy`.
+    huge: bool,
 }
 
+/// Number of 4 KiB pages in one RV64 Sv39 megapage (a level-1 leaf PTE):
+/// `2 MiB / 4 KiB`.
+const HUGE_PAGE_STEP: usize = 512;
+
 impl MapArea {
     pub fn new(
         start_va: VirtAddr,
@@ -482,47 +1006,209 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file_backing: None,
+            lazy: false,
+            huge: false,
         }
     }
+    /// Create a demand-paged area backed by `file`, starting at `offset`
+    /// bytes into it. No frame is allocated and no page table entry is
+    /// installed until [`MemorySet::handle_mmap_fault`] services the first
+    /// access to each page.
+    pub fn new_file_backed(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+        file: Arc<dyn File + Send + Sync>,
+        offset: usize,
+    ) -> Self {
+        let mut area = Self::new(start_va, end_va, MapType::FileBacked, map_perm);
+        area.file_backing = Some(FileBacking { file, offset });
+        area
+    }
+    /// Create a `MapType::Framed` area with no frames allocated and no PTEs
+    /// installed; each page is faulted in lazily by
+    /// [`MemorySet::handle_lazy_fault`] on first access instead.
+    pub fn new_lazy_framed(start_va: VirtAddr, end_va: VirtAddr, map_perm: MapPermission) -> Self {
+        let mut area = Self::new(start_va, end_va, MapType::Framed, map_perm);
+        area.lazy = true;
+        area
+    }
+    /// Create a `MapType::Framed` area backed by 2 MiB megapages (RV64 Sv39
+    /// level-1 leaves) instead of 4 KiB pages, for `MAP_HUGETLB`-style
+    /// mappings. Requires `[start_va, end_va)` to already be 2 MiB-aligned
+    /// on both ends and at least one megapage long; returns `Err` otherwise.
+    pub fn new_huge_framed(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+    ) -> Result<Self, &'static str> {
+        let mut area = Self::new(start_va, end_va, MapType::Framed, map_perm);
+        let start_vpn = area.vpn_range.get_start();
+        let end_vpn = area.vpn_range.get_end();
+        if start_vpn.0 % HUGE_PAGE_STEP != 0 || end_vpn.0 % HUGE_PAGE_STEP != 0 {
+            return Err("MAP_HUGETLB range must be 2 MiB-aligned");
+        }
+        if end_vpn.0 - start_vpn.0 < HUGE_PAGE_STEP {
+            return Err("MAP_HUGETLB range must be at least 2 MiB long");
+        }
+        area.huge = true;
+        Ok(area)
+    }
     pub fn from_another(another: &Self) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file_backing: another.file_backing.clone(),
+            lazy: another.lazy,
+            huge: another.huge,
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    pub fn map_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<(), &'static str> {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
+            // Left unmapped: faulted in lazily by `handle_lazy_fault`.
+            MapType::Framed if self.lazy => return Ok(()),
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or("out of memory: no free physical frames")?;
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
+            // Left unmapped: faulted in lazily by `handle_mmap_fault`.
+            MapType::FileBacked => return Ok(()),
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
+        Ok(())
     }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+            if self.data_frames.remove(&vpn).is_none() && self.lazy {
+                // never faulted in: nothing mapped, nothing to unmap
+                return;
+            }
+        } else if self.map_type == MapType::FileBacked {
+            if let Some(frame) = self.data_frames.remove(&vpn) {
+                self.writeback_one(vpn, &frame);
+            } else {
+                // never faulted in: nothing mapped, nothing to unmap
+                return;
+            }
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// Flush a file-backed page's current contents back to its inode if the
+    /// area is writable and shared (i.e. not a private/read-only mapping).
+    fn writeback_one(&self, vpn: VirtPageNum, frame: &FrameTracker) {
+        if !self.map_perm.contains(MapPermission::W) {
+            return;
+        }
+        if let Some(backing) = &self.file_backing {
+            let page_index = vpn.0 - self.vpn_range.get_start().0;
+            let file_offset = backing.offset + page_index * PAGE_SIZE;
+            backing.file.write_at(file_offset, frame.ppn.get_bytes_array());
+        }
+    }
+    /// Map every page/megapage in this area. On a partial failure mid-range
+    /// (physical memory exhausted), unmaps everything this call already
+    /// installed before returning `Err`, so no half-built area leaks into
+    /// `self.data_frames` or the page table.
+    pub fn map(&mut self, page_table: &mut PageTable) -> Result<(), &'static str> {
+        if self.huge {
+            let mut vpn = self.vpn_range.get_start();
+            let end = self.vpn_range.get_end();
+            while vpn < end {
+                if let Err(e) = self.map_huge_one(page_table, vpn) {
+                    self.unmap_huge_range(page_table, self.vpn_range.get_start(), vpn);
+                    return Err(e);
+                }
+                vpn = VirtPageNum(vpn.0 + HUGE_PAGE_STEP);
+            }
+            return Ok(());
+        }
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if let Err(e) = self.map_one(page_table, vpn) {
+                self.unmap_range(page_table, self.vpn_range.get_start(), vpn);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    /// Unmap every page in `[start, end)`, used to unwind a partially
+    /// installed [`Self::map`] call.
+    fn unmap_range(&mut self, page_table: &mut PageTable, start: VirtPageNum, end: VirtPageNum) {
+        for vpn in VPNRange::new(start, end) {
+            self.unmap_one(page_table, vpn);
+        }
+    }
+    /// Megapage counterpart of [`Self::unmap_range`].
+    fn unmap_huge_range(
+        &mut self,
+        page_table: &mut PageTable,
+        start: VirtPageNum,
+        end: VirtPageNum,
+    ) {
+        let mut vpn = start;
+        while vpn < end {
+            self.unmap_huge_one(page_table, vpn);
+            vpn = VirtPageNum(vpn.0 + HUGE_PAGE_STEP);
         }
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.huge {
+            let mut vpn = self.vpn_range.get_start();
+            let end = self.vpn_range.get_end();
+            while vpn < end {
+                self.unmap_huge_one(page_table, vpn);
+                vpn = VirtPageNum(vpn.0 + HUGE_PAGE_STEP);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
     }
+    /// Map the 2 MiB megapage starting at `vpn` (must be megapage-aligned):
+    /// allocate `HUGE_PAGE_STEP` contiguous frames and install a single
+    /// level-1 leaf PTE spanning all of them, instead of 512 level-0 PTEs.
+    ///
+    /// Depends on two APIs this checkout doesn't have source for:
+    /// `frame_alloc_contiguous` (a contiguous-block allocator in the
+    /// missing `frame_allocator.rs`) and `PageTable::map_huge` (a level-1
+    /// leaf installer in the missing `page_table.rs`, stopping the page
+    /// table walk one level early instead of descending to level 0).
+    fn map_huge_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<(), &'static str> {
+        let frames = super::frame_alloc_contiguous(HUGE_PAGE_STEP)
+            .ok_or("out of memory: insufficient contiguous physical frames")?;
+        let ppn = frames[0].ppn;
+        for (i, frame) in frames.into_iter().enumerate() {
+            self.data_frames.insert(VirtPageNum(vpn.0 + i), Arc::new(frame));
+        }
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.map_huge(vpn, ppn, pte_flags);
+        Ok(())
+    }
+    /// Unmap the 2 MiB megapage starting at `vpn`; see [`Self::map_huge_one`]
+    /// for the same missing-API caveat (`PageTable::unmap_huge`).
+    fn unmap_huge_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        for i in 0..HUGE_PAGE_STEP {
+            self.data_frames.remove(&VirtPageNum(vpn.0 + i));
+        }
+        page_table.unmap_huge(vpn);
+    }
     #[allow(unused)]
     pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
         for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
@@ -531,15 +1217,38 @@ impl MapArea {
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
     #[allow(unused)]
-    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn)
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> bool {
+        let start = self.vpn_range.get_end();
+        for vpn in VPNRange::new(start, new_end) {
+            if self.map_one(page_table, vpn).is_err() {
+                self.unmap_range(page_table, start, vpn);
+                return false;
+            }
         }
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        true
+    }
+    /// Rewrite every currently-mapped page in this area to `new_perm`,
+    /// keeping the same physical frame. Unmapped pages (e.g. a
+    /// `MapType::FileBacked` area not yet faulted in) are left untouched —
+    /// they'll pick up `map_perm` whenever they do fault in. Used by
+    /// [`MemorySet::protect`].
+    pub fn set_perm(&mut self, page_table: &mut PageTable, new_perm: MapPermission) {
+        self.map_perm = new_perm;
+        let new_flags = PTEFlags::from_bits(new_perm.bits).unwrap();
+        for vpn in self.vpn_range {
+            if let Some(pte) = page_table.translate(vpn) {
+                if pte.is_valid() {
+                    let ppn = pte.ppn();
+                    page_table.unmap(vpn);
+                    page_table.map(vpn, ppn, new_flags);
+                }
+            }
+        }
     }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
-    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) -> Result<(), &'static str> {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
         let mut current_vpn = self.vpn_range.get_start();
@@ -548,7 +1257,7 @@ impl MapArea {
             let src = &data[start..len.min(start + PAGE_SIZE)];
             let dst = &mut page_table
                 .translate(current_vpn)
-                .unwrap()
+                .ok_or("copy_data: destination page not mapped")?
                 .ppn()
                 .get_bytes_array()[..src.len()];
             dst.copy_from_slice(src);
@@ -558,6 +1267,7 @@ impl MapArea {
             }
             current_vpn.step();
         }
+        Ok(())
     }
     /// whether a maparea is empty
     #[allow(unused)]
@@ -567,10 +1277,13 @@ impl MapArea {
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
-/// map type for memory set: identical or framed
+/// map type for memory set: identical, framed, or demand-paged from a file
 pub enum MapType {
     Identical,
     Framed,
+    /// Backed by a `File`; see [`MapArea::new_file_backed`] and
+    /// [`MemorySet::handle_mmap_fault`].
+    FileBacked,
 }
 
 bitflags! {
@@ -617,3 +1330,45 @@ pub fn remap_test() {
         .executable(),);
     println!("remap_test passed!");
 }
+
+/// Arbitrary megapage-aligned VA for [`huge_remap_test`]; unrelated to any
+/// real mapping, just far enough below `TRAMPOLINE` to not collide with it.
+const HUGE_REMAP_TEST_BASE: usize = 0x1_0000_0000;
+
+/// Huge-page analogue of [`remap_test`]: map a single `MAP_HUGETLB`
+/// megapage via [`MemorySet::insert_huge_framed_area`] and check it landed
+/// as one level-1 leaf spanning the whole 2 MiB range, instead of 512
+/// independent level-0 PTEs.
+///
+/// `PageTableEntry` doesn't expose which level it was found at (that API
+/// lives in the missing `page_table.rs`, see the caveat on
+/// [`MapArea::map_huge_one`]), so this checks the two observable hallmarks
+/// of a single leaf instead: every page in the range translates to a
+/// physically contiguous run starting at the first page's PPN, and every
+/// one carries the permission bits the area was mapped with.
+#[allow(unused)]
+pub fn huge_remap_test() {
+    let start_va: VirtAddr = HUGE_REMAP_TEST_BASE.into();
+    let end_va: VirtAddr = (HUGE_REMAP_TEST_BASE + HUGE_PAGE_STEP * PAGE_SIZE).into();
+    let perm = MapPermission::R | MapPermission::W | MapPermission::U;
+
+    let mut memory_set = MemorySet::new_bare();
+    memory_set
+        .insert_huge_framed_area(start_va, end_va, perm)
+        .unwrap();
+
+    let base_vpn = start_va.floor();
+    let base_pte = memory_set.page_table.translate(base_vpn).unwrap();
+    assert!(base_pte.is_valid());
+    assert!(base_pte.readable());
+    assert!(base_pte.writable());
+
+    for i in 0..HUGE_PAGE_STEP {
+        let vpn = VirtPageNum(base_vpn.0 + i);
+        let pte = memory_set.page_table.translate(vpn).unwrap();
+        assert!(pte.is_valid());
+        assert_eq!(pte.ppn().0, base_pte.ppn().0 + i);
+    }
+
+    println!("huge_remap_test passed!");
+}
@@ -6,6 +6,7 @@
 //!
 //! Every task or process has a memory_set to control its virtual memory.
 mod address;
+mod asid;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
@@ -14,10 +15,12 @@ mod page_table;
 use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
 use frame_allocator::is_enough;
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
-pub use memory_set::remap_test;
+pub use frame_allocator::{frame_alloc, frame_alloc_contiguous, frame_dealloc, FrameTracker};
+pub use memory_set::{huge_remap_test, remap_test};
 use memory_set::CrossType;
-pub use memory_set::{kernel_token, mmap, munmap, MapPermission, MemorySet, KERNEL_SPACE};
+pub use memory_set::{
+    kernel_token, mmap, mmap_file, mprotect, munmap, MapPermission, MemorySet, KERNEL_SPACE,
+};
 use page_table::PTEFlags;
 pub use page_table::{
     translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
@@ -25,6 +28,58 @@ pub use page_table::{
 };
 
 use crate::task::current_task;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Scatter `src` across however many (possibly non-contiguous) physical
+/// frames `buffers` refers to. `buffers` is normally the output of
+/// [`translated_byte_buffer`], which already knows how to walk the page
+/// table per byte range, so this stays correct even when the destination
+/// straddles a page boundary. Returns the number of bytes copied.
+///
+/// # Safety
+/// `buffers` must describe writable user memory and its total length must
+/// be at least `src.len()`.
+pub unsafe fn copy_to_user(buffers: Vec<&mut [u8]>, src: &[u8]) -> isize {
+    let mut offset = 0;
+    for buffer in buffers {
+        if offset >= src.len() {
+            break;
+        }
+        let n = buffer.len().min(src.len() - offset);
+        buffer[..n].copy_from_slice(&src[offset..offset + n]);
+        offset += n;
+    }
+    offset as isize
+}
+
+/// Write `value` into user space at `ptr`, via [`copy_to_user`].
+///
+/// A plain `*ptr = value` (or [`translated_refmut`]) assumes the whole `T`
+/// lives in one physical frame, which breaks the moment a struct like
+/// `TimeVal`/`TaskInfo` straddles a page boundary in the user address space.
+pub fn write_user<T: 'static + Copy>(token: usize, ptr: *mut T, value: &T) {
+    let len = core::mem::size_of::<T>();
+    let src = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, len) };
+    let buffers = translated_byte_buffer(token, ptr as *const u8, len);
+    unsafe {
+        copy_to_user(buffers, src);
+    }
+}
+
+/// Read a `T` back out of user space at `ptr`, gathering bytes from however
+/// many physical frames back the range. See [`write_user`].
+pub fn read_user<T: 'static + Copy>(token: usize, ptr: *const T) -> T {
+    let len = core::mem::size_of::<T>();
+    let mut bytes = vec![0u8; len];
+    let mut offset = 0;
+    for chunk in translated_byte_buffer(token, ptr as *const u8, len) {
+        let n = chunk.len();
+        bytes[offset..offset + n].copy_from_slice(chunk);
+        offset += n;
+    }
+    unsafe { core::ptr::read(bytes.as_ptr() as *const T) }
+}
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
@@ -91,10 +146,45 @@ pub fn dealloc_check(start: VirtPageNum, end: VirtPageNum) -> Result<CrossType,
 }
 
 /// 为当前运行的进程分配内存
-fn alloc_mm(start: VirtAddr, end: VirtAddr, port: MapPermission) {
+fn alloc_mm(
+    start: VirtAddr,
+    end: VirtAddr,
+    port: MapPermission,
+    populate: bool,
+) -> Result<(), &'static str> {
+    if let Some(task) = current_task() {
+        let mut inner = task.inner_exclusive_access();
+        inner
+            .memory_set
+            .insert_framed_area(start, end, port, populate)
+    } else {
+        panic!("There isn't any running task in Task Manager!")
+    }
+}
+
+/// 为当前运行的进程分配一块大页（2 MiB megapage）内存（`MAP_HUGETLB` 的实现）
+fn alloc_mm_huge(start: VirtAddr, end: VirtAddr, port: MapPermission) -> Result<(), &'static str> {
     if let Some(task) = current_task() {
         let mut inner = task.inner_exclusive_access();
-        inner.memory_set.insert_framed_area(start, end, port);
+        inner.memory_set.insert_huge_framed_area(start, end, port)
+    } else {
+        panic!("There isn't any running task in Task Manager!")
+    }
+}
+
+/// 为当前运行的进程分配一块文件映射内存（mmap 的文件后端版本）
+fn alloc_mm_file(
+    start: VirtAddr,
+    end: VirtAddr,
+    port: MapPermission,
+    file: alloc::sync::Arc<dyn crate::fs::File + Send + Sync>,
+    file_offset: usize,
+) -> Result<(), &'static str> {
+    if let Some(task) = current_task() {
+        let mut inner = task.inner_exclusive_access();
+        inner
+            .memory_set
+            .insert_file_backed_area(start, end, port, file, file_offset)
     } else {
         panic!("There isn't any running task in Task Manager!")
     }
@@ -109,3 +199,30 @@ fn dealloc_mm(start: VirtPageNum, end: VirtPageNum, cross_type: CrossType) {
         panic!("There isn't any running task in Task Manager!")
     }
 }
+
+/// 校验当前运行的进程的某一段用户缓冲区是否可以以 `want` 中的权限访问，
+/// 供各个系统调用在解引用用户指针之前做统一检查
+pub fn check_user_buffer(
+    start: VirtAddr,
+    len: usize,
+    want: MapPermission,
+) -> Result<(), &'static str> {
+    if let Some(task) = current_task() {
+        let inner = task.inner_exclusive_access();
+        inner.memory_set.check_user_access(start, len, want)
+    } else {
+        panic!("There isn't any running task in Task Manager!")
+    }
+}
+
+/// 为当前运行的进程的已有映射修改访问权限（mprotect 的实现）
+fn protect_mm(start: VirtPageNum, end: VirtPageNum, new_perm: MapPermission) -> Result<(), &'static str> {
+    if let Some(task) = current_task() {
+        let mut inner = task.inner_exclusive_access();
+        // mprotect 要求被修改的范围必须已经完整映射，语义上和 munmap 的前置检查一致
+        inner.memory_set.is_vmm_fully_mapped(start, end)?;
+        inner.memory_set.protect(start, end, new_perm)
+    } else {
+        panic!("There isn't any running task in Task Manager!")
+    }
+}
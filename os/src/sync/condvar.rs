@@ -0,0 +1,53 @@
+//! Condition variable
+
+use super::{Mutex, SpinLock};
+use crate::task::{mark_current_blocked, schedule, WaitQueue};
+use alloc::sync::Arc;
+
+struct CondvarInner {
+    wait_queue: WaitQueue,
+}
+
+/// A condition variable, always used alongside a [`Mutex`] the caller
+/// already holds: [`wait`](Self::wait) releases it before parking and
+/// reacquires it before returning, the same contract `pthread_cond_wait`
+/// has.
+pub struct Condvar {
+    inner: SpinLock<CondvarInner>,
+}
+
+impl Condvar {
+    /// Create a new condition variable with no one waiting
+    pub fn new() -> Self {
+        Self {
+            inner: SpinLock::new(CondvarInner {
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Wake the longest-waiting task parked in `wait`, if any
+    pub fn signal(&self) {
+        self.inner.exclusive_access().wait_queue.wakeup_one();
+    }
+
+    /// Release `mutex`, block until [`signal`](Self::signal) wakes us, then
+    /// reacquire `mutex` before returning.
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) {
+        mutex.unlock();
+        let mut inner = self.inner.exclusive_access();
+        // Mark ourselves `Blocked` before parking on the wait queue: see the
+        // ordering requirement documented on `WaitQueue`.
+        let (task, task_cx_ptr) = mark_current_blocked();
+        inner.wait_queue.push(task);
+        drop(inner);
+        schedule(task_cx_ptr);
+        mutex.lock();
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+//! Synchronization primitives
+//!
+//! [`UPSafeCell`] is the original single-hart-only interior-mutability cell:
+//! it's just a `RefCell` with an `unsafe impl Sync` bolted on, so the caller
+//! is promising no two harts ever touch the same cell at once. That promise
+//! stopped holding the moment secondary harts actually started running code
+//! concurrently (see `task::start_secondary_harts`): every static that's
+//! reachable from more than one hart now uses [`SpinLock`] instead, which
+//! provides real mutual exclusion instead of just a marker trait.
+mod condvar;
+mod mutex;
+mod semaphore;
+
+use core::cell::{RefCell, RefMut, UnsafeCell};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use semaphore::{SemId, Semaphore, SemaphoreInner};
+
+/// A single-hart-only interior-mutability cell.
+///
+/// Safe to use only for state that's provably never touched by more than
+/// one hart at a time (e.g. a given hart's own [`task::Processor`](crate::task::Processor)
+/// entry, indexed solely by that hart's own id). Anything reachable from an
+/// arbitrary hart belongs in a [`SpinLock`] instead.
+pub struct UPSafeCell<T> {
+    inner: RefCell<T>,
+}
+
+unsafe impl<T> Sync for UPSafeCell<T> {}
+
+impl<T> UPSafeCell<T> {
+    /// Wrap `value` in a new cell.
+    ///
+    /// # Safety
+    /// The caller must guarantee this cell is never accessed by more than
+    /// one hart concurrently.
+    pub unsafe fn new(value: T) -> Self {
+        Self {
+            inner: RefCell::new(value),
+        }
+    }
+    /// Borrow the inner value exclusively. Panics if already borrowed.
+    pub fn exclusive_access(&self) -> RefMut<'_, T> {
+        self.inner.borrow_mut()
+    }
+}
+
+/// A real cross-hart mutual-exclusion lock: a spinning test-and-set on an
+/// `AtomicBool` guarding an `UnsafeCell`. Unlike [`UPSafeCell`], holding the
+/// guard genuinely serializes access between harts instead of merely
+/// asserting it does.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for SpinLock<T> {}
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// Wrap `value` in a new, unlocked spinlock.
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Spin until the lock is acquired, then return a guard that releases
+    /// it on drop. Named to match [`UPSafeCell::exclusive_access`] so
+    /// callers that switch between the two don't need to change call
+    /// sites, only the static's declared type.
+    pub fn exclusive_access(&self) -> SpinLockGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        SpinLockGuard { lock: self }
+    }
+}
+
+/// RAII guard returned by [`SpinLock::exclusive_access`]; releases the lock
+/// when dropped.
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,144 @@
+//! Mutex
+
+use super::SpinLock;
+use crate::task::{current_task, mark_current_blocked, schedule, WaitQueue};
+
+/// A lock that grants exclusive access to whichever task holds it.
+///
+/// Shared as `Arc<dyn Mutex>` in `process_inner.mutex_list`, so both
+/// [`MutexSpin`] and [`MutexBlocking`] can be handed out behind the same
+/// handle (`sys_mutex_create`'s `blocking` flag picks which one).
+pub trait Mutex: Send + Sync {
+    /// Acquire the lock, blocking (by whichever means this impl uses) until
+    /// it's free.
+    fn lock(&self);
+    /// Release the lock. Caller's responsibility to only call this while
+    /// actually holding it.
+    fn unlock(&self);
+    /// Whether the lock is currently held by anyone.
+    fn is_locking(&self) -> bool;
+    /// The pid of the task currently holding the lock, if any. Used by the
+    /// deadlock detector to build the Allocation row for this resource (see
+    /// `syscall::sync::deadlock`).
+    fn holding_tid(&self) -> Option<usize>;
+}
+
+/// Busy-waiting mutex: spins on a flag rather than parking off the ready
+/// queue. Cheaper than [`MutexBlocking`] for locks held only briefly, since
+/// it avoids the round trip through the scheduler.
+pub struct MutexSpin {
+    inner: SpinLock<Option<usize>>,
+}
+
+impl MutexSpin {
+    /// Create a new unlocked spin mutex
+    pub fn new() -> Self {
+        Self {
+            inner: SpinLock::new(None),
+        }
+    }
+}
+
+impl Default for MutexSpin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) {
+        let pid = current_task().unwrap().getpid();
+        loop {
+            let mut holder = self.inner.exclusive_access();
+            if holder.is_none() {
+                *holder = Some(pid);
+                return;
+            }
+            drop(holder);
+            core::hint::spin_loop();
+        }
+    }
+
+    fn unlock(&self) {
+        *self.inner.exclusive_access() = None;
+    }
+
+    fn is_locking(&self) -> bool {
+        self.inner.exclusive_access().is_some()
+    }
+
+    fn holding_tid(&self) -> Option<usize> {
+        *self.inner.exclusive_access()
+    }
+}
+
+struct MutexBlockingInner {
+    locked: bool,
+    holder: Option<usize>,
+    wait_queue: WaitQueue,
+}
+
+/// Blocking mutex: a contending task parks on [`WaitQueue`] instead of
+/// spinning, so it isn't burning a hart while waiting.
+pub struct MutexBlocking {
+    inner: SpinLock<MutexBlockingInner>,
+}
+
+impl MutexBlocking {
+    /// Create a new unlocked blocking mutex
+    pub fn new() -> Self {
+        Self {
+            inner: SpinLock::new(MutexBlockingInner {
+                locked: false,
+                holder: None,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+}
+
+impl Default for MutexBlocking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.locked {
+            inner.locked = true;
+            inner.holder = Some(current_task().unwrap().getpid());
+            return;
+        }
+        // Mark ourselves `Blocked` before parking on the wait queue: see the
+        // ordering requirement documented on `WaitQueue`.
+        let (task, task_cx_ptr) = mark_current_blocked();
+        inner.wait_queue.push(task);
+        drop(inner);
+        schedule(task_cx_ptr);
+        // Woken by `unlock`, which already set us up as the new holder.
+    }
+
+    fn unlock(&self) {
+        let mut inner = self.inner.exclusive_access();
+        // `wakeup_one` both pops the next waiter and calls `wakeup_task` on
+        // it; handing the lock straight to it (rather than clearing
+        // `locked`) avoids a gap where a third task could sneak in via
+        // `lock()` ahead of the waiter that's already been chosen.
+        if let Some(next) = inner.wait_queue.wakeup_one() {
+            inner.holder = Some(next.getpid());
+        } else {
+            inner.locked = false;
+            inner.holder = None;
+        }
+    }
+
+    fn is_locking(&self) -> bool {
+        self.inner.exclusive_access().locked
+    }
+
+    fn holding_tid(&self) -> Option<usize> {
+        self.inner.exclusive_access().holder
+    }
+}
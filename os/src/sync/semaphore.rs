@@ -1,7 +1,9 @@
 //! Semaphore
 
-use crate::sync::UPSafeCell;
-use crate::task::{block_current_and_run_next, current_task, wakeup_task, TaskControlBlock};
+use crate::sync::SpinLock;
+use crate::task::{add_timer, current_task, now_ms, wakeup_task, TaskControlBlock};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use alloc::{collections::VecDeque, sync::Arc};
 
 /// semaphore Id
@@ -13,12 +15,53 @@ pub struct Semaphore {
     /// semaphore id
     pub sem_id: SemId,
     /// semaphore inner
-    pub inner: UPSafeCell<SemaphoreInner>,
+    ///
+    /// Waiters and holders can be touched by whichever hart is running
+    /// `up`/`down`/`down_timeout` for any task that holds or wants this
+    /// semaphore, so this needs a real cross-hart lock rather than
+    /// `UPSafeCell`'s single-hart-only promise.
+    pub inner: SpinLock<SemaphoreInner>,
 }
 
 pub struct SemaphoreInner {
     pub count: isize,
     pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Tasks currently holding a unit of this semaphore, tracked so a
+    /// blocking waiter can donate its priority to whichever holder is
+    /// standing in its way (see `donate_priority`/`restore_priority`).
+    pub holders: Vec<Arc<TaskControlBlock>>,
+    /// Per-waiter "was I granted the resource?" flag for waiters parked via
+    /// [`Semaphore::down_timeout`], keyed by pid. `up` sets it before waking
+    /// a waiter it grants a unit to; the timed-out waiter checks it after
+    /// resuming to tell a real grant apart from its own timer firing, so a
+    /// task woken by one path is never double-handled by the other.
+    pub woken_flags: BTreeMap<usize, Arc<SpinLock<bool>>>,
+}
+
+/// Priority-inheritance helper: boost `holder`'s effective stride to
+/// `waiter_stride` if that's better (smaller) than what it already has,
+/// saving the value it had before on the holder's inheritance stack so
+/// `restore_priority` can undo it once the holder releases a resource.
+/// Nested holds push multiple entries, which are popped in LIFO order: the
+/// strides unwind correctly when locks are released in the reverse order of
+/// the donations; an out-of-order release restores whichever entry is on top.
+fn donate_priority(holder: &Arc<TaskControlBlock>, waiter_stride: usize) {
+    let mut holder_inner = holder.inner_exclusive_access();
+    if waiter_stride < holder_inner.proc_stride {
+        let original = holder_inner.proc_stride;
+        holder_inner.proc_stride = waiter_stride;
+        holder_inner.stride_inheritance_stack.push(original);
+    }
+}
+
+/// Undo the most recent `donate_priority` applied to `holder`, restoring
+/// whatever stride it had before (which may itself be a donation from an
+/// outer lock still held).
+fn restore_priority(holder: &Arc<TaskControlBlock>) {
+    let mut holder_inner = holder.inner_exclusive_access();
+    if let Some(original) = holder_inner.stride_inheritance_stack.pop() {
+        holder_inner.proc_stride = original;
+    }
 }
 
 impl Semaphore {
@@ -27,12 +70,12 @@ impl Semaphore {
         trace!("kernel: Semaphore::new");
         Self {
             sem_id: SemId(sem_id),
-            inner: unsafe {
-                UPSafeCell::new(SemaphoreInner {
-                    count: res_count as isize,
-                    wait_queue: VecDeque::new(),
-                })
-            },
+            inner: SpinLock::new(SemaphoreInner {
+                count: res_count as isize,
+                wait_queue: VecDeque::new(),
+                holders: Vec::new(),
+                woken_flags: BTreeMap::new(),
+            }),
         }
     }
 
@@ -43,15 +86,13 @@ impl Semaphore {
         inner.count += 1;
 
         let current_task = current_task().unwrap();
-        let task_inner = current_task.inner_exclusive_access();
 
-        if inner.count <= 0 {
-            if let Some(task) = inner.wait_queue.pop_front() {
-                wakeup_task(task);
-            }
+        // releasing a held unit: drop the releasing task from the holder set
+        // and undo the most recent boost on its inheritance stack.
+        if let Some(pos) = inner.holders.iter().position(|t| Arc::ptr_eq(t, &current_task)) {
+            inner.holders.remove(pos);
         }
-
-        drop(task_inner);
+        restore_priority(&current_task);
         drop(current_task);
 
         if inner.count <= 0 {
@@ -83,6 +124,10 @@ impl Semaphore {
                 }
 
                 drop(task_inner);
+                inner.holders.push(task.clone());
+                if let Some(flag) = inner.woken_flags.remove(&task.getpid()) {
+                    *flag.exclusive_access() = true;
+                }
                 wakeup_task(task);
             }
         }
@@ -107,11 +152,24 @@ impl Semaphore {
             } else {
                 task_inner.need.push((self.sem_id.clone(), 1))
             }
+            let waiter_stride = task_inner.proc_stride;
 
             drop(task_inner);
+            // priority inheritance: every current holder blocking us gets
+            // boosted to our stride if we're the better-priority waiter.
+            for holder in inner.holders.iter() {
+                if !Arc::ptr_eq(holder, &current_task) {
+                    donate_priority(holder, waiter_stride);
+                }
+            }
+            // Mark ourselves `Blocked` *before* registering as a waiter: a
+            // concurrent `up()` that lands between the push and the status
+            // flip would otherwise see us not-yet-`Blocked` and silently
+            // drop the wakeup via `wakeup_task`'s `Blocked`-only guard.
+            let (_task, task_cx_ptr) = crate::task::mark_current_blocked();
             inner.wait_queue.push_back(current_task);
             drop(inner);
-            block_current_and_run_next();
+            crate::task::schedule(task_cx_ptr);
         } else {
             if let Some(alloc_count) = task_inner
                 .allocation
@@ -122,6 +180,103 @@ impl Semaphore {
             } else {
                 task_inner.allocation.push((self.sem_id.clone(), 1));
             }
+            drop(task_inner);
+            inner.holders.push(current_task);
+        }
+    }
+
+    /// Like [`Semaphore::down`], but gives up after `ms` milliseconds
+    /// instead of blocking forever. Returns `true` if a unit was acquired,
+    /// `false` on timeout. On timeout, the caller is pulled back out of
+    /// `wait_queue` and the `need` row it registered is rolled back, so the
+    /// deadlock-detection matrices stay consistent with reality.
+    pub fn down_timeout(&self, ms: usize) -> bool {
+        trace!("kernel: Semaphore::down_timeout");
+        let mut inner = self.inner.exclusive_access();
+        inner.count -= 1;
+
+        let current_task = current_task().unwrap();
+        let pid = current_task.getpid();
+        let mut task_inner = current_task.inner_exclusive_access();
+
+        if inner.count >= 0 {
+            if let Some(alloc_count) = task_inner
+                .allocation
+                .iter_mut()
+                .find(|(sem_id, _)| *sem_id == self.sem_id)
+            {
+                alloc_count.1 += 1;
+            } else {
+                task_inner.allocation.push((self.sem_id.clone(), 1));
+            }
+            drop(task_inner);
+            inner.holders.push(current_task);
+            return true;
+        }
+
+        if let Some(sem_count) = task_inner
+            .need
+            .iter_mut()
+            .find(|(sem_id, _)| *sem_id == self.sem_id)
+        {
+            sem_count.1 += 1;
+        } else {
+            task_inner.need.push((self.sem_id.clone(), 1))
+        }
+        let waiter_stride = task_inner.proc_stride;
+        drop(task_inner);
+
+        for holder in inner.holders.iter() {
+            if !Arc::ptr_eq(holder, &current_task) {
+                donate_priority(holder, waiter_stride);
+            }
+        }
+
+        let woken = Arc::new(SpinLock::new(false));
+        inner.woken_flags.insert(pid, woken.clone());
+        // Mark ourselves `Blocked` *before* registering in `wait_queue`/the
+        // timer wheel: an `up()` or timer firing that lands in the gap
+        // between registration and the status flip would otherwise be
+        // dropped as a no-op by `wakeup_task`'s `Blocked`-only guard, and
+        // we'd block forever despite already having been woken.
+        let (task, task_cx_ptr) = crate::task::mark_current_blocked();
+        inner.wait_queue.push_back(task.clone());
+        add_timer(now_ms() + ms, task);
+        drop(inner);
+        crate::task::schedule(task_cx_ptr);
+
+        if *woken.exclusive_access() {
+            return true;
+        }
+
+        // Our timer fired before `up` granted us the unit: pull ourselves
+        // back out and undo the `need` bookkeeping we added above. If `up`
+        // raced us and already removed our entry, `woken_flags` no longer
+        // has us and the grant stands instead.
+        let mut inner = self.inner.exclusive_access();
+        if inner.woken_flags.remove(&pid).is_none() {
+            return true;
+        }
+        if let Some(pos) = inner
+            .wait_queue
+            .iter()
+            .position(|t| Arc::ptr_eq(t, &current_task))
+        {
+            inner.wait_queue.remove(pos);
+        }
+        inner.count += 1;
+        let mut task_inner = current_task.inner_exclusive_access();
+        if let Some((index, sem_count)) = task_inner
+            .need
+            .iter_mut()
+            .enumerate()
+            .find(|(_, (sem_id, _))| *sem_id == self.sem_id)
+        {
+            sem_count.1 -= 1;
+            if sem_count.1 <= 0 {
+                task_inner.need.remove(index);
+            }
         }
+        false
     }
 }
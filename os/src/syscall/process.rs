@@ -5,11 +5,11 @@ use core::ops::Sub;
 use crate::{
     config::MAX_SYSCALL_NUM,
     fs::{open_file, OpenFlags},
-    mm::{mmap, munmap, translated_refmut, translated_str},
+    mm::{mmap, mmap_file, mprotect, munmap, translated_refmut, translated_str},
     task::{
         add_task, current_task, current_user_token, exit_current_and_run_next,
-        get_current_task_info, get_time_task, set_proc_prio, suspend_current_and_run_next,
-        TaskStatus,
+        get_current_task_info, get_time_task, set_proc_prio, sleep_current_for,
+        suspend_current_and_run_next, TaskStatus,
     },
     timer::get_time_us,
 };
@@ -74,6 +74,14 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("Unreachable in sys_exit!");
 }
 
+/// block the current task until `ms` milliseconds have elapsed, without
+/// busy-spinning: the task is woken by the timer wheel instead of by polling
+pub fn sys_sleep(ms: usize) -> isize {
+    trace!("kernel:pid[{}] sys_sleep", current_task().unwrap().pid.0);
+    sleep_current_for(ms);
+    0
+}
+
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
     trace!("kernel:pid[{}] sys_yield", current_task().unwrap().pid.0);
@@ -89,7 +97,13 @@ pub fn sys_getpid() -> isize {
 pub fn sys_fork() -> isize {
     trace!("kernel:pid[{}] sys_fork", current_task().unwrap().pid.0);
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let new_task = match current_task.fork() {
+        Ok(new_task) => new_task,
+        Err(message) => {
+            println!("[kernel] sys_fork error!!!, message: {:?}", message);
+            return -1;
+        }
+    };
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
@@ -108,8 +122,13 @@ pub fn sys_exec(path: *const u8) -> isize {
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
         let all_data = app_inode.read_all();
         let task = current_task().unwrap();
-        task.exec(all_data.as_slice());
-        0
+        match task.exec(all_data.as_slice()) {
+            Ok(_) => 0,
+            Err(message) => {
+                println!("[kernel] sys_exec error!!!, message: {:?}", message);
+                -1
+            }
+        }
     } else {
         -1
     }
@@ -175,9 +194,11 @@ pub fn kernel_get_time(ts: *mut TimeVal, _tz: usize) {
     }
 }
 
-/// YOUR JOB: get time with second and microsecond
-/// HINT: You might reimplement it with virtual memory management.
-/// HINT: What if [`TimeVal`] is splitted by two pages ?
+/// Get time with second and microsecond.
+///
+/// Writes through [`crate::mm::write_user`] so the result lands correctly
+/// even when `TimeVal` straddles a page boundary in the caller's address
+/// space.
 pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     trace!(
         "kernel:pid[{}] sys_get_time NOT IMPLEMENTED",
@@ -187,9 +208,11 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     0
 }
 
-/// YOUR JOB: Finish sys_task_info to pass testcases
-/// HINT: You might reimplement it with virtual memory management.
-/// HINT: What if [`TaskInfo`] is splitted by two pages ?
+/// Fill in the current task's [`TaskInfo`].
+///
+/// Writes through [`crate::mm::write_user`] so the result lands correctly
+/// even when `TaskInfo` straddles a page boundary in the caller's address
+/// space.
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
     trace!(
         "kernel:pid[{}] sys_task_info NOT IMPLEMENTED",
@@ -199,16 +222,72 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
     0
 }
 
-/// YOUR JOB: Implement mmap.
-pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
+/// Bit in `sys_mmap`'s `flags`: prefault the whole range eagerly instead of
+/// leaving anonymous pages unmapped until they're touched.
+pub const MAP_POPULATE: usize = 1 << 0;
+/// Bit in `sys_mmap`'s `flags`: back the anonymous range with 2 MiB
+/// megapages instead of 4 KiB pages (see
+/// [`crate::mm::MemorySet::insert_huge_framed_area`]). Overrides
+/// `MAP_POPULATE`, which has no separate meaning for huge pages.
+pub const MAP_HUGETLB: usize = 1 << 1;
+
+/// Map `len` bytes starting at `start` with the given `port` permission
+/// bits. Anonymous by default; pass `fd >= 0` to instead demand-page the
+/// mapping in from that file descriptor (content read lazily, page by
+/// page, starting at `file_offset` bytes into the file — see
+/// [`crate::mm::MemorySet::handle_mmap_fault`]). An anonymous mapping is
+/// itself lazy — each page is left unmapped and faulted in individually by
+/// [`crate::mm::MemorySet::handle_lazy_fault`] — unless `flags` has
+/// [`MAP_POPULATE`] set, which maps every page up front as before, or
+/// [`MAP_HUGETLB`] set, which backs it with megapages instead.
+pub fn sys_mmap(
+    start: usize,
+    len: usize,
+    port: usize,
+    fd: isize,
+    file_offset: usize,
+    flags: usize,
+) -> isize {
+    trace!("kernel:pid[{}] sys_mmap", current_task().unwrap().pid.0);
+    let result = if fd < 0 {
+        mmap(
+            start,
+            len,
+            port,
+            flags & MAP_POPULATE != 0,
+            flags & MAP_HUGETLB != 0,
+        )
+    } else {
+        let task = current_task().unwrap();
+        let inner = task.inner_exclusive_access();
+        let file = match inner.fd_table.get(fd as usize).and_then(|f| f.clone()) {
+            Some(file) => file,
+            None => return -1,
+        };
+        drop(inner);
+        mmap_file(start, len, port, file, file_offset)
+    };
+    match result {
+        Ok(_) => 0,
+        Err(message) => {
+            println!("[kernel] sys_map error!!!, message: {:?}", message);
+            -1
+        }
+    }
+}
+
+/// Change the `port` permission bits of an already-mapped `[start, start+len)`
+/// in place, without unmapping and re-mapping it (so e.g. toggling a region
+/// between R/W and R/X preserves its contents).
+pub fn sys_mprotect(start: usize, len: usize, port: usize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_mmap NOT IMPLEMENTED",
+        "kernel:pid[{}] sys_mprotect",
         current_task().unwrap().pid.0
     );
-    match mmap(start, len, port) {
+    match mprotect(start, len, port) {
         Ok(_) => 0,
         Err(message) => {
-            println!("[kernel] sys_map error!!!, message: {:?}", message);
+            println!("[kernel] sys_mprotect error!!!, message: {:?}", message);
             -1
         }
     }
@@ -250,7 +329,13 @@ pub fn sys_spawn(path: *const u8) -> isize {
     if let Some(app_inode) = open_file(app_path.as_str(), OpenFlags::RDONLY) {
         let all_data = app_inode.read_all();
         let current_task = current_task().unwrap();
-        let spawn_task = current_task.spawn(&all_data);
+        let spawn_task = match current_task.spawn(&all_data) {
+            Ok(spawn_task) => spawn_task,
+            Err(message) => {
+                println!("[kernel] sys_spawn error!!!, message: {:?}", message);
+                return -1;
+            }
+        };
         let pid = spawn_task.pid.0;
 
         let trap_cx = spawn_task.inner_exclusive_access().get_trap_cx();
@@ -264,13 +349,20 @@ pub fn sys_spawn(path: *const u8) -> isize {
     }
 }
 
-// YOUR JOB: Set task priority.
+/// Set the current task's stride-scheduling priority.
+///
+/// `prio` must be at least 2: the stride scheduler divides `BIG_STRIDE` by
+/// the priority to get the task's pass, so `prio == 0` would divide by zero,
+/// and `prio == 1` would give a pass of the whole `BIG_STRIDE`, above the
+/// `BIG_STRIDE / 2` bound that the scheduler's wrapping stride comparison
+/// counts on.
 pub fn sys_set_priority(prio: isize) -> isize {
     trace!(
-        "kernel:pid[{}] sys_set_priority NOT IMPLEMENTED",
-        current_task().unwrap().pid.0
+        "kernel:pid[{}] sys_set_priority({})",
+        current_task().unwrap().pid.0,
+        prio
     );
-    if prio <= 2 {
+    if prio < 2 {
         return -1;
     }
     set_proc_prio(prio as usize);
@@ -0,0 +1,42 @@
+//! Minimal cooperative executor for futures that may suspend on device I/O
+//!
+//! `easy-fs`'s `get_block_cache_async`/`BlockCache::new_async` return a
+//! `Future` that only resolves once the backing `AsyncBlockDevice` signals
+//! completion. A task stuck waiting on that has nothing better to do than
+//! give up the CPU, so [`block_on`] polls the future and, whenever it isn't
+//! ready yet, yields via [`super::suspend_current_and_run_next`] (exactly
+//! like `sys_yield`) instead of busy-waiting, so other ready tasks can run
+//! while the disk request is in flight.
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+fn noop_raw_waker() -> RawWaker {
+    fn clone(_: *const ()) -> RawWaker {
+        noop_raw_waker()
+    }
+    fn no_op(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+    RawWaker::new(core::ptr::null(), &VTABLE)
+}
+
+/// Drive `future` to completion on the current task. There's no real
+/// completion signal to sleep on yet (this kernel has no interrupt-driven
+/// block device), so this amounts to cooperative re-polling: every time
+/// `future` isn't ready, hand the CPU to another task via
+/// `suspend_current_and_run_next` and try again once rescheduled. A real
+/// async driver would instead stash `cx.waker()` and wake the task straight
+/// from its completion interrupt.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = future;
+    // Safety: `future` is never moved again after this point.
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => super::suspend_current_and_run_next(),
+        }
+    }
+}
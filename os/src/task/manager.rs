@@ -1,36 +1,15 @@
 //!Implementation of [`TaskManager`]
+use super::scheduler::{Scheduler, StrideScheduler};
 use super::TaskControlBlock;
-use crate::sync::UPSafeCell;
-use alloc::collections::binary_heap::BinaryHeap;
+use crate::sync::SpinLock;
 use alloc::sync::Arc;
-use core::cmp::Reverse;
 use lazy_static::*;
 
-struct TcbPtr(Arc<TaskControlBlock>);
-
-impl PartialEq for TcbPtr {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.inner_exclusive_access().proc_stride == other.0.inner_exclusive_access().proc_stride
-    }
-}
-
-impl Eq for TcbPtr {}
-
-impl PartialOrd for TcbPtr {
-    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
-        let self_stride = self.0.inner_exclusive_access().proc_stride;
-        let other_stride = other.0.inner_exclusive_access().proc_stride;
-        Some(self_stride.cmp(&other_stride))
-    }
-}
-
-impl Ord for TcbPtr {
-    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        let self_stride = self.0.inner_exclusive_access().proc_stride;
-        let other_stride = other.0.inner_exclusive_access().proc_stride;
-        self_stride.cmp(&other_stride)
-    }
-}
+/// The ready-queue policy actually plugged into [`TASK_MANAGER`].
+///
+/// Swapping this alias for [`super::scheduler::FifoScheduler`] switches the
+/// whole kernel back to plain round-robin scheduling.
+type ActiveScheduler = StrideScheduler<Arc<TaskControlBlock>>;
 
 ///A array of `TaskControlBlock` that is thread-safe
 pub struct TaskManager {
@@ -38,36 +17,39 @@ pub struct TaskManager {
     // needs to be put in/taken out, and if the task control block itself is moved directly,
     // there will be a lot of data copy overhead.
     // [destingfvcker] And under some case, it can make out implementation more convinient
-    ready_queue: BinaryHeap<Reverse<TcbPtr>>,
+    ready_queue: ActiveScheduler,
 }
 
-/// A RR scheduler.
+/// A scheduler-agnostic ready queue: the actual ordering policy lives in `ready_queue`.
 impl TaskManager {
     ///Creat an empty TaskManager
     pub fn new() -> Self {
         Self {
-            ready_queue: BinaryHeap::new(),
+            ready_queue: ActiveScheduler::new(),
         }
     }
     /// Add process back to ready queue
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        let tcb_ptr = TcbPtr(task);
-        self.ready_queue.push(Reverse(tcb_ptr));
+        self.ready_queue.insert(task);
     }
     /// Take a process out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        if let Some(Reverse(tcb_ptr)) = self.ready_queue.pop() {
-            Some(tcb_ptr.0)
-        } else {
-            None
-        }
+        self.ready_queue.pop()
+    }
+    /// Remove a specific ready task, e.g. one that was killed before it ran again
+    #[allow(unused)]
+    pub fn remove(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.remove(pid)
     }
 }
 
 lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    ///
+    /// Every hart's idle loop calls `fetch_task`/`add_task` on this same
+    /// ready queue, so it needs a real cross-hart lock (`SpinLock`), not
+    /// `UPSafeCell`'s single-hart-only promise.
+    pub static ref TASK_MANAGER: SpinLock<TaskManager> = SpinLock::new(TaskManager::new());
 }
 
 /// Add process to ready queue
@@ -81,3 +63,14 @@ pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     //trace!("kernel: TaskManager::fetch_task");
     TASK_MANAGER.exclusive_access().fetch()
 }
+
+/// Pull a specific still-ready task back out of the ready queue by pid, e.g.
+/// one that was killed by its parent before it ever got to run again.
+///
+/// No caller yet: this tree has no `sys_kill`, so nothing can mark another
+/// task for termination while it's still sitting in the ready queue. Kept
+/// around `#[allow(unused)]` for whichever syscall adds that ability.
+#[allow(unused)]
+pub fn remove_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().remove(pid)
+}
@@ -14,31 +14,39 @@
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 mod context;
+mod executor;
 mod id;
 mod manager;
 mod processor;
+mod scheduler;
+mod smp;
 mod switch;
 mod task;
+mod wait;
 
 #[allow(clippy::module_inception)]
 #[allow(rustdoc::private_intra_doc_links)]
 use crate::fs::{open_file, OpenFlags};
 use crate::{
     config::BIG_STRIDE,
-    mm::translated_refmut,
+    mm::write_user,
     syscall::{kernel_get_time, TaskInfo, TimeVal},
 };
 use alloc::sync::Arc;
 pub use context::TaskContext;
 use core::panic;
+pub use executor::block_on;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
 use lazy_static::*;
 pub use manager::add_task;
-pub use manager::{fetch_task, TaskManager};
+pub use manager::{fetch_task, remove_task, TaskManager};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, set_proc_prio,
-    take_current_task, Processor,
+    current_task, current_trap_cx, current_user_token, hart_id, run_tasks, schedule,
+    set_proc_prio, take_current_task, Processor,
 };
+pub use scheduler::{FifoScheduler, Schedulable, Scheduler, StrideScheduler};
+pub use smp::{start_hart, start_secondary_harts};
+pub use wait::{add_timer, check_timers, now_ms, sleep_current_for, WaitQueue};
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
 /// Suspend the current 'Running' task and run the next task in task list.
@@ -61,6 +69,58 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// Block the current 'Running' task and run the next task in task list.
+///
+/// Unlike `suspend_current_and_run_next`, the task is *not* put back on the
+/// ready queue: it sits in `TaskStatus::Blocked` until something (a
+/// `sys_sleep` deadline, a wait queue, ...) calls [`wakeup_task`] on it.
+pub fn block_current_and_run_next() {
+    let (_task, task_cx_ptr) = mark_current_blocked();
+    schedule(task_cx_ptr);
+}
+
+/// Take the current task off this hart and mark it `Blocked`, but don't
+/// switch away yet.
+///
+/// Split out of `block_current_and_run_next` so a caller that needs to
+/// register the task as a waiter somewhere (a wait queue, the timer wheel,
+/// ...) can do so *after* the status flip instead of before it: `wakeup_task`
+/// only acts on a task that's already `Blocked`, so registering first and
+/// flipping the status second leaves a window where a wake that lands in
+/// between is silently dropped as a no-op and the task never wakes up. The
+/// caller must finish by calling [`schedule`] with the returned pointer.
+pub fn mark_current_blocked() -> (Arc<TaskControlBlock>, *mut TaskContext) {
+    let task = take_current_task().unwrap();
+    let task_cx_ptr = mark_task_blocked(&task);
+    (task, task_cx_ptr)
+}
+
+/// Flip `task`'s status to `Blocked` and return a pointer to its context for
+/// `schedule`, without switching away. See [`mark_current_blocked`].
+fn mark_task_blocked(task: &Arc<TaskControlBlock>) -> *mut TaskContext {
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.task_status = TaskStatus::Blocked;
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
+    task_cx_ptr
+}
+
+/// Move a previously-blocked task back onto the ready queue.
+///
+/// A no-op if `task` isn't currently `Blocked`: a timed wait (see
+/// `sync::Semaphore::down_timeout`) can be woken by either its timer or the
+/// resource becoming available, and whichever happens second must not
+/// re-queue a task that's already `Ready`/`Running`.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
+
 /// pid of usertests app in make run TEST=1
 pub const IDLE_PID: usize = 0;
 
@@ -110,6 +170,10 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     inner.memory_set.recycle_data_pages();
     drop(inner);
     // +++++++ release current PCB
+    // Drop any futex waits this task still has queued (e.g. it exited
+    // without ever being woken) so they don't leak or collide with a
+    // future, unrelated task that reuses the same physical address.
+    crate::syscall::sync::futex_cleanup_exited_task(pid);
     // drop task manually to maintain rc correctly
     drop(task);
     // we do not have to save task context
@@ -125,7 +189,7 @@ lazy_static! {
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
         let inode = open_file("ch6b_initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        TaskControlBlock::new(v.as_slice())
+        TaskControlBlock::new(v.as_slice()).expect("out of memory while creating initproc")
     });
 }
 
@@ -153,8 +217,7 @@ pub fn get_current_task_info(ti: *mut TaskInfo) {
         let inner = task.inner_exclusive_access();
         let task_info = inner.get_task_info();
 
-        let user_ptr = translated_refmut(inner.memory_set.token(), ti);
-        *user_ptr = task_info;
+        write_user(inner.memory_set.token(), ti, &task_info);
     } else {
         panic!(
             "Try to get current running task info, \
@@ -171,8 +234,7 @@ pub fn get_time_task(ts: *mut TimeVal) {
 
         kernel_get_time(&mut sys_time as *mut TimeVal, usize::default());
 
-        let user_ptr = translated_refmut(inner.memory_set.token(), ts);
-        *user_ptr = sys_time;
+        write_user(inner.memory_set.token(), ts, &sys_time);
     } else {
         panic!("There isn't any running task!")
     }
@@ -3,29 +3,54 @@
 //! Here, the continuous operation of user apps in CPU is maintained,
 //! the current running state of CPU is recorded,
 //! and the replacement and transfer of control flow of different applications are executed.
+//!
+//! The kernel boots one [`Processor`] per hart (see [`PROCESSORS`]), so every
+//! hart can independently fetch from the shared [`super::TASK_MANAGER`] ready
+//! queue and run its own idle loop; `tp` is expected to hold this hart's id
+//! (set by the boot assembly before jumping into Rust) so [`hart_id`] can
+//! find the right entry without taking a lock.
 
 use super::__switch;
-use super::{fetch_task, TaskStatus};
+use super::{check_timers, fetch_task, TaskStatus};
 use super::{TaskContext, TaskControlBlock};
+use crate::config::MAX_HART_NUM;
 use crate::sync::UPSafeCell;
 use crate::syscall::{kernel_get_time, TimeVal};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::arch::asm;
 use lazy_static::*;
 
-/// Processor management structure
+/// Read this hart's id out of `tp`.
+///
+/// Boot code is responsible for setting `tp = hart_id` before control
+/// reaches any Rust code that might call this (both the boot hart in
+/// `entry.asm` and every secondary hart started through `start_hart`).
+pub fn hart_id() -> usize {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+    }
+    tp
+}
+
+/// Per-hart processor management structure
 pub struct Processor {
-    ///The task currently executing on the current processor
+    /// This hart's id
+    hart_id: usize,
+    ///The task currently executing on this hart
     current: Option<Arc<TaskControlBlock>>,
 
-    ///The basic control flow of each core, helping to select and switch process
+    ///The basic control flow of this hart, helping to select and switch process
     idle_task_cx: TaskContext,
 }
 
 impl Processor {
-    ///Create an empty Processor
-    pub fn new() -> Self {
+    ///Create an empty Processor for the given hart
+    pub fn new(hart_id: usize) -> Self {
         Self {
+            hart_id,
             current: None,
             idle_task_cx: TaskContext::zero_init(),
         }
@@ -48,7 +73,29 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One `Processor` per hart, indexed by [`hart_id`].
+    ///
+    /// This stays a plain [`UPSafeCell`] rather than a [`crate::sync::SpinLock`]:
+    /// unlike `TASK_MANAGER`/`TIMERS`/etc., no entry is ever reachable from
+    /// more than one hart. [`this_processor`] only ever indexes
+    /// `PROCESSORS[hart_id()]` — the calling hart's own slot — so two harts
+    /// can run `exclusive_access()` concurrently without ever touching the
+    /// same cell. The `debug_assert!` there checks that the hart id is in
+    /// range.
+    static ref PROCESSORS: Vec<UPSafeCell<Processor>> = (0..MAX_HART_NUM)
+        .map(|id| unsafe { UPSafeCell::new(Processor::new(id)) })
+        .collect();
+}
+
+/// Access the calling hart's own `Processor`.
+///
+/// Always indexes by the calling hart's own id, so it's never possible for
+/// two harts to both resolve to the same `Processor` and race on its
+/// `UPSafeCell` — see the invariant documented on [`PROCESSORS`].
+fn this_processor() -> &'static UPSafeCell<Processor> {
+    let id = hart_id();
+    debug_assert!(id < PROCESSORS.len(), "hart id {} out of range", id);
+    &PROCESSORS[id]
 }
 
 // [liuzl 2024年10月26日10:28:00]
@@ -70,11 +117,20 @@ lazy_static! {
 //         .map(|id| (id, inner.tasks[id].task_status))
 // }
 
-///The main part of process execution and scheduling
+///The main part of process execution and scheduling, run once per hart.
 ///Loop `fetch_task` to get the process that needs to run, and switch the process through `__switch`
+///
+///`TASK_MANAGER`'s ready queue is shared across harts, so two harts racing to
+///`fetch_task` can never be handed the same `Arc`: `fetch_task` pops the task
+///out of the queue under `TASK_MANAGER`'s lock, and the hart then installs it
+///as `this_processor().current`.
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        // Wake up any task whose `sys_sleep` deadline has passed. Ideally
+        // this runs from the timer interrupt; polling it here too means a
+        // sleeper is never stuck if interrupts are coalesced.
+        check_timers();
+        let mut processor = this_processor().exclusive_access();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
@@ -100,19 +156,19 @@ pub fn run_tasks() {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
         } else {
-            warn!("no tasks available in run_tasks");
+            warn!("hart {}: no tasks available in run_tasks", processor.hart_id);
         }
     }
 }
 
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    this_processor().exclusive_access().take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    this_processor().exclusive_access().current()
 }
 
 /// Get the current user token(addr of page table)
@@ -129,9 +185,9 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
-///Return to idle control flow for new scheduling
+///Return to this hart's idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = this_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
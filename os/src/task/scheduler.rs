@@ -0,0 +1,133 @@
+//! Pluggable ready-queue scheduling policies used by [`super::manager::TaskManager`]
+//!
+//! `TaskManager` only drives `insert`/`peek`/`pop`/`remove`; it does not know or
+//! care which order tasks come back out in. That lets us swap in different
+//! policies (plain FIFO, or priority-proportional stride scheduling) without
+//! touching the manager itself — the same split the tornado-os scheduler
+//! crate uses to keep its ready queue policy-agnostic.
+use super::TaskControlBlock;
+use crate::config::BIG_STRIDE;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Anything a [`Scheduler`] can order and look back up by pid.
+pub trait Schedulable {
+    /// Unique id used by [`Scheduler::remove`] to find this task again
+    fn sched_pid(&self) -> usize;
+    /// Current accumulated stride (ignored by policies that don't need it)
+    fn stride(&self) -> usize;
+    /// The task's pass (`BIG_STRIDE / proc_prio`), the amount its stride
+    /// advances each time it gives up the CPU
+    fn pass(&self) -> usize;
+}
+
+impl Schedulable for Arc<TaskControlBlock> {
+    fn sched_pid(&self) -> usize {
+        self.getpid()
+    }
+    fn stride(&self) -> usize {
+        self.inner_exclusive_access().proc_stride
+    }
+    fn pass(&self) -> usize {
+        let inner = self.inner_exclusive_access();
+        BIG_STRIDE / inner.proc_prio
+    }
+}
+
+/// A ready-queue scheduling policy.
+pub trait Scheduler<T: Schedulable> {
+    /// Create an empty scheduler
+    fn new() -> Self;
+    /// Insert a ready task
+    fn insert(&mut self, task: T);
+    /// Look at the task that would be dispatched next, without removing it
+    fn peek(&self) -> Option<&T>;
+    /// Remove and return the task that should be dispatched next
+    fn pop(&mut self) -> Option<T>;
+    /// Remove a specific ready task (e.g. a task killed while still ready)
+    fn remove(&mut self, pid: usize) -> Option<T>;
+}
+
+/// Plain round-robin FIFO: tasks run in the order they became ready.
+pub struct FifoScheduler<T: Schedulable> {
+    queue: VecDeque<T>,
+}
+
+impl<T: Schedulable> Scheduler<T> for FifoScheduler<T> {
+    fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+    fn insert(&mut self, task: T) {
+        self.queue.push_back(task);
+    }
+    fn peek(&self) -> Option<&T> {
+        self.queue.front()
+    }
+    fn pop(&mut self) -> Option<T> {
+        self.queue.pop_front()
+    }
+    fn remove(&mut self, pid: usize) -> Option<T> {
+        let idx = self.queue.iter().position(|t| t.sched_pid() == pid)?;
+        self.queue.remove(idx)
+    }
+}
+
+/// Returns whether `a`'s stride is "smaller" than `b`'s, tolerating `u64`
+/// wraparound: the gap between any two in-flight strides is always below
+/// `BIG_STRIDE` (no pass exceeds `BIG_STRIDE / 2`, since `proc_prio >= 2`), so reading the
+/// wrapping difference as a signed value recovers the true ordering even
+/// after an overflow.
+fn stride_lt(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+/// Stride scheduler: always dispatches the ready task with the numerically
+/// smallest `proc_stride` (wrapping-aware). It does not change strides
+/// itself: `suspend_current_and_run_next` adds the task's pass to its stride
+/// when it gives up the CPU, so the others get a turn.
+pub struct StrideScheduler<T: Schedulable> {
+    ready: VecDeque<T>,
+}
+
+impl<T: Schedulable> StrideScheduler<T> {
+    /// Index of the ready task with the minimal stride, if any
+    fn min_index(&self) -> Option<usize> {
+        self.ready
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                if stride_lt(a.stride(), b.stride()) {
+                    core::cmp::Ordering::Less
+                } else if stride_lt(b.stride(), a.stride()) {
+                    core::cmp::Ordering::Greater
+                } else {
+                    core::cmp::Ordering::Equal
+                }
+            })
+            .map(|(idx, _)| idx)
+    }
+}
+
+impl<T: Schedulable> Scheduler<T> for StrideScheduler<T> {
+    fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+        }
+    }
+    fn insert(&mut self, task: T) {
+        self.ready.push_back(task);
+    }
+    fn peek(&self) -> Option<&T> {
+        self.min_index().map(|idx| &self.ready[idx])
+    }
+    fn pop(&mut self) -> Option<T> {
+        let idx = self.min_index()?;
+        self.ready.remove(idx)
+    }
+    fn remove(&mut self, pid: usize) -> Option<T> {
+        let idx = self.ready.iter().position(|t| t.sched_pid() == pid)?;
+        self.ready.remove(idx)
+    }
+}
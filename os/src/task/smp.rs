@@ -0,0 +1,44 @@
+//! Boot secondary harts through the SBI Hart State Management (HSM) extension
+//!
+//! Hart 0 is already running Rust code by the time `rust_main` executes;
+//! every other hart is parked in SBI firmware until we explicitly start it
+//! here, pointing it at its own entry stub (which must set `tp` to its hart
+//! id, per [`super::processor::hart_id`], before falling into `run_tasks`).
+use core::arch::asm;
+
+const SBI_EID_HSM: usize = 0x4853_4D;
+const SBI_HSM_HART_START: usize = 0;
+
+/// Ask SBI to start `hartid` executing at `start_addr`, with `opaque` passed
+/// through in `a1` at the entry point. Returns the raw SBI error code (`0`
+/// on success).
+pub fn start_hart(hartid: usize, start_addr: usize, opaque: usize) -> isize {
+    let error: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            in("a0") hartid,
+            in("a1") start_addr,
+            in("a2") opaque,
+            in("a6") SBI_HSM_HART_START,
+            in("a7") SBI_EID_HSM,
+            lateout("a0") error,
+        );
+    }
+    error
+}
+
+/// Boot every secondary hart (`1..num_harts`) at `entry`. `opaque` is
+/// typically the physical address of a table the entry stub indexes with
+/// its hart id to find its own boot stack.
+pub fn start_secondary_harts(num_harts: usize, entry: usize, opaque: usize) {
+    for hartid in 1..num_harts {
+        let err = start_hart(hartid, entry, opaque);
+        if err != 0 {
+            println!(
+                "[kernel] failed to start hart {}: sbi error {}",
+                hartid, err
+            );
+        }
+    }
+}
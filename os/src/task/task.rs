@@ -4,13 +4,12 @@ use super::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
 use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT_BASE};
 use crate::fs::{File, Stdin, Stdout};
 use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
+use crate::sync::{SpinLock, SpinLockGuard};
 use crate::syscall::{kernel_get_time, TaskInfo, TimeVal};
 use crate::trap::{trap_handler, TrapContext};
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::cell::RefMut;
 
 // In ch4:
 // The task control block (TCB) of a task.
@@ -38,12 +37,17 @@ pub struct TaskControlBlock {
     pub kernel_stack: KernelStack,
 
     /// Mutable
-    inner: UPSafeCell<TaskControlBlockInner>,
+    ///
+    /// A task's `Arc` is routinely shared across harts (parent/child during
+    /// fork, the hart that's running it vs. whichever hart wakes it up), so
+    /// this needs a real cross-hart lock rather than `UPSafeCell`'s
+    /// single-hart-only promise.
+    inner: SpinLock<TaskControlBlockInner>,
 }
 
 impl TaskControlBlock {
     /// Get the mutable reference of the inner TCB
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, TaskControlBlockInner> {
         self.inner.exclusive_access()
     }
     /// Get the address of app's page table
@@ -96,6 +100,12 @@ pub struct TaskControlBlockInner {
 
     /// process's stride
     pub proc_stride: usize,
+
+    /// Original `proc_stride` values temporarily overridden by priority
+    /// inheritance (see `sync::Semaphore`; `Mutex` does not donate priority). Pushed when this task,
+    /// as a lock holder, has its stride boosted to match a higher-priority
+    /// waiter; popped on release so nested lock holds unwind correctly.
+    pub stride_inheritance_stack: Vec<usize>,
 }
 
 impl TaskControlBlockInner {
@@ -141,9 +151,12 @@ impl TaskControlBlock {
     /// Create a new process
     ///
     /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8]) -> Self {
+    ///
+    /// Fails if physical memory runs out while building `elf_data`'s address
+    /// space (see [`MemorySet::from_elf`]).
+    pub fn new(elf_data: &[u8]) -> Result<Self, &'static str> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
             .unwrap()
@@ -156,32 +169,31 @@ impl TaskControlBlock {
         let task_control_block = Self {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::UnInit,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                    heap_bottom: user_sp,
-                    program_brk: user_sp,
-                    syscall_cnt: [0; MAX_SYSCALL_NUM],
-                    start_up_time: TimeVal::default(),
-                    proc_prio: 16,
-                    proc_stride: 0,
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx_ppn,
+                base_size: user_sp,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::UnInit,
+                memory_set,
+                parent: None,
+                children: Vec::new(),
+                exit_code: 0,
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stdout)),
+                ],
+                heap_bottom: user_sp,
+                program_brk: user_sp,
+                syscall_cnt: [0; MAX_SYSCALL_NUM],
+                start_up_time: TimeVal::default(),
+                proc_prio: 16,
+                proc_stride: 0,
+                stride_inheritance_stack: Vec::new(),
+            }),
         };
         // prepare TrapContext in user space
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
@@ -192,13 +204,18 @@ impl TaskControlBlock {
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        Ok(task_control_block)
     }
 
     /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
+    ///
+    /// Fails without touching the running task if physical memory runs out
+    /// while building `elf_data`'s address space: the old `memory_set` is
+    /// only swapped out once the new one is fully built, so a caller that
+    /// gets `Err` back keeps running on its previous image.
+    pub fn exec(&self, elf_data: &[u8]) -> Result<(), &'static str> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
             .unwrap()
@@ -222,14 +239,18 @@ impl TaskControlBlock {
         );
         *inner.get_trap_cx() = trap_cx;
         // **** release current PCB
+        Ok(())
     }
 
     /// parent process fork the child process
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    ///
+    /// Fails if physical memory runs out while deep-copying a non-COW area
+    /// of the parent's address space (see [`MemorySet::from_existed_user`]).
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Result<Arc<TaskControlBlock>, &'static str> {
         // ---- hold parent PCB lock
         let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        // copy-on-write user space (include trap context)
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT_BASE).into())
             .unwrap()
@@ -250,25 +271,24 @@ impl TaskControlBlock {
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::UnInit,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: new_fd_table,
-                    heap_bottom: parent_inner.heap_bottom,
-                    program_brk: parent_inner.program_brk,
-                    syscall_cnt: parent_inner.syscall_cnt.clone(), // 这里在逻辑上是继承父进程的系统调用数量统计还是覆盖？
-                    start_up_time: TimeVal::default(),
-                    proc_prio: 16,
-                    proc_stride: 0,
-                })
-            },
+            inner: SpinLock::new(TaskControlBlockInner {
+                trap_cx_ppn,
+                base_size: parent_inner.base_size,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                task_status: TaskStatus::UnInit,
+                memory_set,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                fd_table: new_fd_table,
+                heap_bottom: parent_inner.heap_bottom,
+                program_brk: parent_inner.program_brk,
+                syscall_cnt: parent_inner.syscall_cnt.clone(), // 这里在逻辑上是继承父进程的系统调用数量统计还是覆盖？
+                start_up_time: TimeVal::default(),
+                proc_prio: 16,
+                proc_stride: 0,
+                stride_inheritance_stack: Vec::new(),
+            }),
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
@@ -277,14 +297,17 @@ impl TaskControlBlock {
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Ok(task_control_block)
         // **** release child PCB
         // ---- release parent PCB
     }
 
     /// spawn a new process by elf_data provided by user
-    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
-        let spawn_task_control_block = Arc::new(TaskControlBlock::new(elf_data));
+    ///
+    /// Fails if physical memory runs out while building `elf_data`'s address
+    /// space (see [`TaskControlBlock::new`]).
+    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Result<Arc<Self>, &'static str> {
+        let spawn_task_control_block = Arc::new(TaskControlBlock::new(elf_data)?);
 
         let mut parent_inner = self.inner_exclusive_access();
         parent_inner.children.push(spawn_task_control_block.clone());
@@ -294,7 +317,7 @@ impl TaskControlBlock {
 
         drop(inner); // 这里为什么当时要写drop?
                      // return
-        spawn_task_control_block
+        Ok(spawn_task_control_block)
     }
 
     /// get pid of process
@@ -330,7 +353,7 @@ impl TaskControlBlock {
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Blocked, Zombie
 pub enum TaskStatus {
     /// uninitialized
     UnInit,
@@ -338,6 +361,9 @@ pub enum TaskStatus {
     Ready,
     /// running
     Running,
+    /// parked off the ready queue, waiting on a condition (lock, timer, ...)
+    /// until something calls `wakeup_task` on it
+    Blocked,
     /// exited
     Zombie,
 }
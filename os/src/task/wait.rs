@@ -0,0 +1,134 @@
+//! Blocking wait queues and a deadline-ordered timer wheel
+//!
+//! Gives tasks a way to give up the CPU without busy-spinning: a `WaitQueue`
+//! parks tasks that are waiting on some condition (a lock, input, ...) off
+//! the ready queue, and [`add_timer`]/[`check_timers`] let `sys_sleep` wake a
+//! parked task once its deadline (measured with the existing
+//! `kernel_get_time`) has passed, instead of having the caller poll it.
+use super::{add_task, TaskControlBlock, TaskStatus};
+use crate::sync::SpinLock;
+use crate::syscall::{kernel_get_time, TimeVal};
+use alloc::collections::{BinaryHeap, VecDeque};
+use alloc::sync::Arc;
+use core::cmp::Reverse;
+use lazy_static::*;
+
+/// FIFO queue of tasks parked on some condition. `WaitQueue` itself only
+/// tracks who is waiting; the caller is responsible for actually blocking.
+///
+/// The caller must mark the current task `Blocked` (see
+/// [`mark_current_blocked`](super::mark_current_blocked)) *before* calling
+/// [`push`](Self::push), then finish with
+/// [`schedule`](super::schedule). Pushing first and blocking second leaves a
+/// window where a wake that lands in between is silently dropped by
+/// [`wakeup_task`](super::wakeup_task)'s `Blocked`-only guard, losing it for
+/// good.
+#[derive(Default)]
+pub struct WaitQueue {
+    waiters: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    /// Create an empty wait queue
+    pub const fn new() -> Self {
+        Self {
+            waiters: VecDeque::new(),
+        }
+    }
+    /// Record `task` as waiting on this queue. See the ordering requirement
+    /// documented on [`WaitQueue`].
+    pub fn push(&mut self, task: Arc<TaskControlBlock>) {
+        self.waiters.push_back(task);
+    }
+    /// Wake the longest-waiting task, if any
+    pub fn wakeup_one(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let task = self.waiters.pop_front()?;
+        super::wakeup_task(task.clone());
+        Some(task)
+    }
+    /// Wake every waiting task
+    pub fn wakeup_all(&mut self) {
+        while self.wakeup_one().is_some() {}
+    }
+    /// Whether any task is currently parked here
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+}
+
+/// Current time in milliseconds, in the same clock [`add_timer`]'s
+/// `expire_ms` is measured in.
+pub fn now_ms() -> usize {
+    let mut tv = TimeVal::default();
+    kernel_get_time(&mut tv as *mut TimeVal, 0);
+    tv.sec * 1000 + tv.usec / 1000
+}
+
+struct TimerEntry {
+    expire_ms: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+impl Eq for TimerEntry {}
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.expire_ms.cmp(&other.expire_ms)
+    }
+}
+
+lazy_static! {
+    /// Tasks parked by `sys_sleep`, ordered by deadline so `check_timers`
+    /// only has to look at the earliest one.
+    ///
+    /// `add_timer` can be called from whichever hart the sleeping task
+    /// happens to run on, while `check_timers` is polled from every hart's
+    /// idle loop, so this needs a real cross-hart lock rather than
+    /// `UPSafeCell`'s single-hart-only promise.
+    static ref TIMERS: SpinLock<BinaryHeap<Reverse<TimerEntry>>> =
+        SpinLock::new(BinaryHeap::new());
+}
+
+/// Park `task` until `expire_ms` (in the same clock as `kernel_get_time`)
+/// has passed. The caller must still block the task itself.
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    TIMERS
+        .exclusive_access()
+        .push(Reverse(TimerEntry { expire_ms, task }));
+}
+
+/// Wake every task whose deadline has passed. Meant to be driven by the
+/// timer interrupt (and is also polled once per idle loop iteration in
+/// [`super::run_tasks`] as a fallback).
+pub fn check_timers() {
+    let now = now_ms();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(Reverse(entry)) = timers.peek() {
+        if entry.expire_ms > now {
+            break;
+        }
+        let Reverse(entry) = timers.pop().unwrap();
+        super::wakeup_task(entry.task);
+    }
+}
+
+/// Block the current task until `ms` milliseconds have elapsed.
+pub fn sleep_current_for(ms: usize) {
+    // Mark the task `Blocked` *before* registering it in `TIMERS`: a timer
+    // that fires between registration and the status flip would be dropped
+    // as a no-op by `wakeup_task`'s `Blocked`-only guard, losing the wakeup
+    // for good (a real risk once another hart can run `check_timers`
+    // concurrently with this one still setting up the sleep).
+    let (task, task_cx_ptr) = super::mark_current_blocked();
+    add_timer(now_ms() + ms, task);
+    super::schedule(task_cx_ptr);
+}